@@ -0,0 +1,440 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::http::{expand_env, Auth, HttpOptions, Response};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// On-disk cache of HTTP responses.
+///
+/// A response younger than the TTL, or than the `max-age` of its
+/// `Cache-Control` header, is used without contacting the server. Older
+/// ones are revalidated with `If-None-Match` / `If-Modified-Since` when the
+/// server sent an `ETag` or `Last-Modified` header. Responses marked
+/// `no-store`, and those of authenticated requests unless allowed, are not
+/// cached.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    refresh: bool,
+    authenticated: bool,
+}
+
+/// Metadata of a cached response, stored next to its body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    /// Seconds since the unix epoch the response was fetched or revalidated.
+    pub fetched_at: u64,
+    pub size: u64,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+            refresh: false,
+            authenticated: false,
+        }
+    }
+
+    /// `$XQ_CACHE_DIR`, or `xq` in `$XDG_CACHE_HOME` or `~/.cache`.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("XQ_CACHE_DIR") {
+            return Some(PathBuf::from(dir));
+        }
+        let dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(dir.join("xq"))
+    }
+
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// How long a response is used without revalidation, one hour by default.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Download again even when a fresh response is cached.
+    pub fn with_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Also cache the responses of requests with credentials, an `auth` or
+    /// an `Authorization` or `Cookie` header.
+    pub fn with_authenticated(mut self, authenticated: bool) -> Self {
+        self.authenticated = authenticated;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Every cached response, most recently fetched first. Unreadable
+    /// entries are skipped, as lookups take them for misses.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for file in std::fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let entry = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice(&data)?));
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => tracing::warn!("skipping cache entry {}: {}", path.display(), e),
+            }
+        }
+        entries.sort_by_key(|e: &CacheEntry| std::cmp::Reverse(e.fetched_at));
+        Ok(entries)
+    }
+
+    /// Remove every cached response, returning how many there were.
+    pub fn clear(&self) -> Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        for file in std::fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => {
+                    std::fs::remove_file(path)?;
+                    count += 1;
+                }
+                Some("body") => std::fs::remove_file(path)?,
+                _ => {}
+            }
+        }
+        Ok(count)
+    }
+
+    /// Fetch `url`, using or revalidating the cached response if any.
+    pub(crate) async fn fetch(&self, http: &HttpOptions, url: &str) -> Result<Response> {
+        if !self.authenticated && http.has_credentials() {
+            tracing::debug!("not caching the authenticated request for {}", url);
+            return http.send(&http.client()?, url).await;
+        }
+        let key = key(http, url)?;
+        let cached = match self.refresh {
+            true => None,
+            false => self.read(&key),
+        };
+
+        let mut conditions = HeaderMap::new();
+        if let Some((entry, body)) = cached.as_ref() {
+            let age = now().saturating_sub(entry.fetched_at);
+            let headers = entry.header_map();
+            let ttl = max_age(&headers).unwrap_or(self.ttl);
+            if Duration::from_secs(age) < ttl {
+                tracing::debug!("using cached response for {}", url);
+                return Ok(entry.response(body.clone()));
+            }
+            if let Some(etag) = headers.get(ETAG) {
+                conditions.insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(modified) = headers.get(LAST_MODIFIED) {
+                conditions.insert(IF_MODIFIED_SINCE, modified.clone());
+            }
+        }
+
+        let resp = http.send_with(&http.client()?, url, conditions).await?;
+        if no_store(&resp.headers) {
+            tracing::debug!("not caching {}, marked no-store", url);
+            return Ok(resp);
+        }
+        if resp.status == StatusCode::NOT_MODIFIED {
+            let (mut entry, body) =
+                cached.ok_or_else(|| anyhow!("Unexpected 304 Not Modified from {}", url))?;
+            tracing::debug!("cached response for {} is still valid", url);
+            entry.fetched_at = now();
+            self.write(&key, &entry, None)?;
+            return Ok(entry.response(body));
+        }
+
+        let entry = CacheEntry {
+            url: url.to_string(),
+            fetched_at: now(),
+            size: resp.body.len() as u64,
+            headers: resp
+                .headers
+                .iter()
+                .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+                .collect(),
+        };
+        self.write(&key, &entry, Some(&resp.body))?;
+        Ok(resp)
+    }
+
+    fn read(&self, key: &str) -> Option<(CacheEntry, Vec<u8>)> {
+        let entry = std::fs::read(self.dir.join(format!("{}.json", key))).ok()?;
+        let body = std::fs::read(self.dir.join(format!("{}.body", key))).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&entry).ok()?;
+        // a body replaced by a concurrent write belongs to another entry
+        (entry.size == body.len() as u64).then_some((entry, body))
+    }
+
+    fn write(&self, key: &str, entry: &CacheEntry, body: Option<&[u8]>) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // the body goes first, an entry is only visible once its metadata is
+        if let Some(body) = body {
+            self.write_file(&format!("{}.body", key), body)?;
+        }
+        self.write_file(&format!("{}.json", key), &serde_json::to_vec(entry)?)
+    }
+
+    /// Write `name` through a temporary file renamed over it, so readers
+    /// never see it half written.
+    fn write_file(&self, name: &str, data: &[u8]) -> Result<()> {
+        let tmp = self
+            .dir
+            .join(format!(".{}.{}.tmp", name, std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, self.dir.join(name)).inspect_err(|_| {
+            std::fs::remove_file(&tmp).ok();
+        })?;
+        Ok(())
+    }
+}
+
+impl CacheEntry {
+    fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(k, v)| {
+                Some((
+                    HeaderName::from_bytes(k.as_bytes()).ok()?,
+                    HeaderValue::from_str(v).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    fn response(&self, body: Vec<u8>) -> Response {
+        Response {
+            status: StatusCode::OK,
+            headers: self.header_map(),
+            body,
+        }
+    }
+}
+
+/// Responses differ by method, body, headers, user agent and credentials
+/// as well as by URL. Values are hashed as sent, after `${NAME}` expansion.
+fn key(http: &HttpOptions, url: &str) -> Result<String> {
+    let expand = |v: &Option<String>| v.as_deref().map(expand_env).transpose();
+    let mut parts = vec![
+        Some(http.method.as_deref().unwrap_or("GET").to_uppercase()),
+        Some(url.to_string()),
+        expand(&http.body)?,
+        expand(&http.user_agent)?,
+    ];
+    match &http.auth {
+        Some(Auth::Bearer(token)) => {
+            parts.extend([Some("bearer".into()), Some(expand_env(token)?)])
+        }
+        Some(Auth::Basic { username, password }) => parts.extend([
+            Some("basic".into()),
+            Some(expand_env(username)?),
+            expand(password)?,
+        ]),
+        None => parts.push(None),
+    }
+    for (name, value) in &http.headers {
+        parts.extend([Some(name.to_lowercase()), Some(expand_env(value)?)]);
+    }
+
+    // FNV-1a, stable across builds unlike the std hasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.unwrap_or_default().bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// The directives of a `Cache-Control` header, lowercased.
+fn cache_control(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|d| d.trim().to_lowercase())
+        .collect()
+}
+
+fn no_store(headers: &HeaderMap) -> bool {
+    cache_control(headers).iter().any(|d| d == "no-store")
+}
+
+/// How long a response is fresh by its `Cache-Control`: its `max-age`, or
+/// none at all with `no-cache`.
+fn max_age(headers: &HeaderMap) -> Option<Duration> {
+    cache_control(headers)
+        .iter()
+        .find_map(|d| match d.split_once('=') {
+            Some(("max-age", secs)) => secs.trim_matches('"').parse().ok().map(Duration::from_secs),
+            None if d == "no-cache" => Some(Duration::ZERO),
+            _ => None,
+        })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn cache_and_revalidate() {
+        let dir = std::env::temp_dir().join("xq_test_cache");
+        let cache = Cache::new(&dir);
+        cache.clear().unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(path("/data.csv"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/data.csv"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string("a\n1"),
+            )
+            .expect(2)
+            .with_priority(2)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/data.csv", server.uri());
+        let http = HttpOptions::default();
+
+        // fetched, then served from the cache without a request
+        assert_eq!(cache.fetch(&http, &url).await.unwrap().body, b"a\n1");
+        assert_eq!(cache.fetch(&http, &url).await.unwrap().body, b"a\n1");
+
+        // stale, revalidated with the ETag and answered with a 304
+        let stale = cache.clone().with_ttl(Duration::ZERO);
+        assert_eq!(stale.fetch(&http, &url).await.unwrap().body, b"a\n1");
+
+        // refresh downloads again
+        let refresh = cache.clone().with_refresh(true);
+        assert_eq!(refresh.fetch(&http, &url).await.unwrap().body, b"a\n1");
+
+        // a corrupt entry is skipped rather than failing the listing
+        std::fs::write(dir.join("corrupt.json"), b"{").unwrap();
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, url);
+        assert_eq!(entries[0].size, 3);
+
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cache_control_and_credentials() {
+        let dir = std::env::temp_dir().join("xq_test_cache_control");
+        let cache = Cache::new(&dir);
+        cache.clear().unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(path("/private"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a\n1"))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(path("/live"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Cache-Control", "no-store")
+                    .set_body_string("a\n1"),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(path("/stale"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Cache-Control", "public, max-age=0")
+                    .set_body_string("a\n1"),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let bearer = HttpOptions::default().with_auth(Auth::Bearer("t".into()));
+        let http = HttpOptions::default();
+        for (http, name) in [(&bearer, "private"), (&http, "live"), (&http, "stale")] {
+            let url = format!("{}/{}", server.uri(), name);
+            for _ in 0..2 {
+                assert_eq!(cache.fetch(http, &url).await.unwrap().body, b"a\n1");
+            }
+        }
+        // only the response without credentials or no-store was cached
+        assert_eq!(cache.entries().unwrap().len(), 1);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn cache_key() {
+        let get = HttpOptions::default();
+        let post = HttpOptions::default().with_method("POST");
+        let key = |http: &HttpOptions, url| key(http, url).unwrap();
+        assert_eq!(key(&get, "http://a/b"), key(&get, "http://a/b"));
+        assert_ne!(key(&get, "http://a/b"), key(&get, "http://a/c"));
+        assert_ne!(key(&get, "http://a/b"), key(&post, "http://a/b"));
+
+        // credentials and user agents as sent, after expansion
+        std::env::set_var("XQ_TEST_CACHE_TOKEN", "one");
+        let bearer =
+            HttpOptions::default().with_auth(Auth::Bearer("${XQ_TEST_CACHE_TOKEN}".into()));
+        let first = key(&bearer, "http://a/b");
+        std::env::set_var("XQ_TEST_CACHE_TOKEN", "two");
+        assert_ne!(first, key(&bearer, "http://a/b"));
+        assert_ne!(
+            key(&get, "http://a/b"),
+            key(&get.clone().with_user_agent("x"), "http://a/b")
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+/// Compression formats xq decompresses transparently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Zip,
+}
+
+impl Compression {
+    /// Detect the compression of `data` from its magic bytes, falling back
+    /// to the extension of `location`.
+    pub fn detect(location: &str, data: &[u8]) -> Option<Self> {
+        Self::from_magic(data).or_else(|| Self::from_extension(location))
+    }
+
+    fn from_magic(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Self::Zstd),
+            [b'B', b'Z', b'h', ..] => Some(Self::Bzip2),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(Self::Xz),
+            [b'P', b'K', 0x03, 0x04, ..] => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    fn from_extension(location: &str) -> Option<Self> {
+        let ext = Path::new(location).extension()?.to_str()?;
+        match ext.to_lowercase().as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            "bz2" => Some(Self::Bzip2),
+            "xz" => Some(Self::Xz),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    /// Map an HTTP `Content-Encoding` value, `None` for `identity`.
+    pub fn from_content_encoding(encoding: &str) -> Result<Option<Self>> {
+        match encoding.trim().to_lowercase().as_str() {
+            "" | "identity" => Ok(None),
+            "gzip" | "x-gzip" => Ok(Some(Self::Gzip)),
+            "zstd" => Ok(Some(Self::Zstd)),
+            "bzip2" => Ok(Some(Self::Bzip2)),
+            "xz" => Ok(Some(Self::Xz)),
+            v => Err(anyhow!("Content-Encoding {} is not supported", v)),
+        }
+    }
+
+    /// Decompress `data`. For zip archives `member` names the file to
+    /// extract, and may be omitted when the archive holds a single file.
+    pub fn decompress(self, data: &[u8], member: Option<&str>) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Gzip => {
+                flate2::read::MultiGzDecoder::new(data).read_to_end(&mut buf)?;
+            }
+            Self::Zstd => {
+                zstd::stream::read::Decoder::new(data)?.read_to_end(&mut buf)?;
+            }
+            Self::Bzip2 => {
+                bzip2::read::MultiBzDecoder::new(data).read_to_end(&mut buf)?;
+            }
+            Self::Xz => {
+                xz2::read::XzDecoder::new_multi_decoder(data).read_to_end(&mut buf)?;
+            }
+            Self::Zip => {
+                let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+                let files: Vec<_> = archive
+                    .file_names()
+                    .filter(|name| !name.ends_with('/'))
+                    .map(str::to_string)
+                    .collect();
+                let name = match (member, files.as_slice()) {
+                    (Some(name), _) => name.to_string(),
+                    (None, [name]) => name.clone(),
+                    (None, _) => {
+                        return Err(anyhow!(
+                            "zip archive has {} files, pick one with #<name>: {}",
+                            files.len(),
+                            files.join(", ")
+                        ))
+                    }
+                };
+                archive
+                    .by_name(&name)
+                    .with_context(|| format!("{} not found in zip archive", name))?
+                    .read_to_end(&mut buf)?;
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// Decompress `data` read from `location` if it is compressed.
+pub fn decompress(location: &str, data: Vec<u8>, member: Option<&str>) -> Result<Vec<u8>> {
+    match Compression::detect(location, &data) {
+        Some(compression) => compression.decompress(&data, member).with_context(|| {
+            format!(
+                "Failed to decompress {:?} data from {}",
+                compression, location
+            )
+        }),
+        None => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DATA: &[u8] = b"name,age\nAlice,20\nBob,22\n";
+
+    #[test]
+    fn decompress_by_magic_bytes() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(DATA).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let zst = zstd::encode_all(DATA, 0).unwrap();
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(DATA).unwrap();
+        let bz = bz.finish().unwrap();
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(DATA).unwrap();
+        let xz = xz.finish().unwrap();
+
+        for (data, compression) in [
+            (gz, Compression::Gzip),
+            (zst, Compression::Zstd),
+            (bz, Compression::Bzip2),
+            (xz, Compression::Xz),
+        ] {
+            assert_eq!(Compression::detect("data", &data), Some(compression));
+            assert_eq!(decompress("data", data, None).unwrap(), DATA);
+        }
+
+        assert_eq!(decompress("data.csv", DATA.to_vec(), None).unwrap(), DATA);
+    }
+
+    #[test]
+    fn decompress_zip_member() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for name in ["a.csv", "b.csv"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(DATA).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+
+        assert_eq!(
+            decompress("a.zip", zip.clone(), Some("b.csv")).unwrap(),
+            DATA
+        );
+        assert!(decompress("a.zip", zip.clone(), None).is_err());
+        assert!(decompress("a.zip", zip, Some("c.csv")).is_err());
+    }
+
+    #[test]
+    fn content_encoding() {
+        assert_eq!(
+            Compression::from_content_encoding("gzip").unwrap(),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_content_encoding("identity").unwrap(),
+            None
+        );
+        assert!(Compression::from_content_encoding("br").is_err());
+    }
+}
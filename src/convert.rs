@@ -1,16 +1,26 @@
+use std::collections::BTreeSet;
+use std::fmt;
 use std::sync::Arc;
 
+use crate::loader::select;
 use anyhow::{anyhow, Ok, Result};
 use polars::lazy::dsl::AggExpr;
-use polars::prelude::{col, len, Expr, LiteralValue, Operator};
+use polars::prelude::{
+    col, len, lit, AnyValue, DataFrame, DataType, Expr, GetOutput, IntoSeries, LiteralValue,
+    Operator, PolarsResult, Schema, Series, StringChunked,
+};
 use sqlparser::ast::{
-    BinaryOperator as SqlBinaryOperator, Expr as SqlExpr, Offset as SqlOffset, OrderByExpr, Select,
-    SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, Value as SqlValue,
+    BinaryOperator as SqlBinaryOperator, Expr as SqlExpr, FunctionArg, FunctionArgExpr, Ident,
+    JsonOperator, Offset as SqlOffset, OrderByExpr, Select, SelectItem, SetExpr, Statement,
+    TableAlias, TableFactor, TableWithJoins, UnaryOperator, Value as SqlValue,
 };
 
 pub struct Sql<'a> {
     pub selection: Vec<Expr>,
-    pub source: &'a str,
+    pub source: DataSource<'a>,
+    /// List columns of `UNNEST(col)`, exploded into a row per element
+    /// before filtering.
+    pub unnest: Vec<String>,
     pub condition: Option<Expr>,
     pub group_by: Vec<Expr>,
     pub aggregation: Vec<Expr>,
@@ -19,6 +29,25 @@ pub struct Sql<'a> {
     pub limit: Option<usize>,
 }
 
+/// Where a query reads its data from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataSource<'a> {
+    /// A URI such as `file:///data.csv` or `https://example.com/data.json`.
+    Uri(&'a str),
+    /// A table function such as `sqlite('app.db', 'events')`.
+    Function(TableFunction),
+    /// Rows of a `(VALUES ...) AS t(a, b)` list.
+    Values(DataFrame),
+}
+
+/// A table function call in `FROM`, its arguments must be literals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFunction {
+    pub name: String,
+    pub args: Vec<SqlValue>,
+    pub named_args: Vec<(String, SqlValue)>,
+}
+
 pub struct Expression(Box<SqlExpr>);
 pub struct Operation(SqlBinaryOperator);
 pub struct Projection<'a>(&'a SelectItem);
@@ -28,6 +57,7 @@ pub struct Offset<'a>(&'a SqlOffset);
 pub struct Limit<'a>(&'a SqlExpr);
 pub struct Value(SqlValue);
 pub struct GroupBy<'a>(&'a SqlExpr);
+pub struct Rows<'a>(&'a [Vec<SqlExpr>], Option<&'a TableAlias>);
 
 impl TryFrom<Expression> for Expr {
     type Error = anyhow::Error;
@@ -42,12 +72,151 @@ impl TryFrom<Expression> for Expr {
             // SqlExpr::IsNull(expr) => Ok(Self::IsNull(Box::new(Expression(expr).try_into()?))),
             // SqlExpr::IsNotNull(expr) => Ok(Self::IsNotNull(Box::new(Expression(expr).try_into()?))),
             SqlExpr::Identifier(id) => Ok(Self::Column(Arc::from(id.value))),
+            // `user.address.city`, resolved against the schema once loaded
+            SqlExpr::CompoundIdentifier(ids) => {
+                let names: Vec<_> = ids.into_iter().map(|id| id.value).collect();
+                Ok(col(&names.join(".")))
+            }
+            SqlExpr::Nested(expr) => Expression(expr).try_into(),
             SqlExpr::Value(v) => Ok(Self::Literal(Value(v).try_into()?)),
+            // `tags[0]` on lists, `user['name']` on structs
+            SqlExpr::MapAccess { column, keys } => {
+                let mut expr: Expr = Expression(column).try_into()?;
+                for key in keys {
+                    expr = match key {
+                        SqlExpr::Value(SqlValue::Number(n, _)) => {
+                            expr.list().get(lit(n.parse::<i64>()?), true)
+                        }
+                        SqlExpr::Value(SqlValue::SingleQuotedString(name)) => {
+                            expr.struct_().field_by_name(&name)
+                        }
+                        key => return Err(anyhow!("Unsupported subscript {}", key)),
+                    };
+                }
+                Ok(expr)
+            }
+            // `payload -> 'user' ->> 'name'` on JSON strings, the last
+            // operator tells whether JSON text or a scalar comes out
+            SqlExpr::JsonAccess {
+                left,
+                operator: operator @ (JsonOperator::Arrow | JsonOperator::LongArrow),
+                right,
+            } => {
+                let expr: Expr = Expression(left).try_into()?;
+                let path = json_path(&right)?;
+                Ok(match last_operator(operator, &right) {
+                    JsonOperator::LongArrow => expr.str().json_path_match(lit(path)),
+                    _ => json_value(expr, path),
+                })
+            }
+            SqlExpr::Function(f)
+                if matches!(
+                    f.name.to_string().to_lowercase().as_str(),
+                    "json_extract" | "json_extract_string"
+                ) =>
+            {
+                let args: Vec<_> = f
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr),
+                        _ => None,
+                    })
+                    .collect();
+                let [expr, path] = args.as_slice() else {
+                    return Err(anyhow!("{} takes a column and a JSON path", f.name));
+                };
+                let expr: Expr = Expression(Box::new((*expr).clone())).try_into()?;
+                Ok(expr.str().json_path_match(lit(json_path(path)?)))
+            }
             v => Err(anyhow!("expr {:#?} is not supported", v)),
         }
     }
 }
 
+fn last_operator(operator: JsonOperator, right: &SqlExpr) -> JsonOperator {
+    match right {
+        SqlExpr::JsonAccess {
+            operator, right, ..
+        } => last_operator(*operator, right),
+        _ => operator,
+    }
+}
+
+/// The JSON text at `path` of JSON strings, with strings kept quoted, as
+/// `->` gives it.
+fn json_value(expr: Expr, path: String) -> Expr {
+    expr.map(
+        move |s| {
+            let values: StringChunked = s
+                .str()?
+                .into_iter()
+                .map(|v| {
+                    let value: serde_json::Value = serde_json::from_str(v?).ok()?;
+                    select(&value, &path)
+                        .filter(|v| !v.is_null())
+                        .map(|v| v.to_string())
+                })
+                .collect();
+            PolarsResult::Ok(Some(values.with_name(s.name()).into_series()))
+        },
+        GetOutput::from_type(DataType::String),
+    )
+}
+
+/// The JSONPath of the right side of `->`: a path such as `'$.a.b'`, a
+/// key, an index, or a chain of them (`'a' -> 0`).
+fn json_path(expr: &SqlExpr) -> Result<String> {
+    match expr {
+        SqlExpr::Value(SqlValue::SingleQuotedString(s)) if s.starts_with('$') => Ok(s.clone()),
+        SqlExpr::Value(SqlValue::SingleQuotedString(s)) => {
+            Ok(format!("$['{}']", s.replace('\'', "\\'")))
+        }
+        SqlExpr::Value(SqlValue::Number(n, _)) => Ok(format!("$[{}]", n.parse::<usize>()?)),
+        SqlExpr::JsonAccess {
+            left,
+            operator: JsonOperator::Arrow | JsonOperator::LongArrow,
+            right,
+        } => {
+            let right = json_path(right)?;
+            Ok(format!("{}{}", json_path(left)?, &right[1..]))
+        }
+        expr => Err(anyhow!("Unsupported JSON path {}", expr)),
+    }
+}
+
+/// Resolve dotted column names missing from `schema` to fields of struct
+/// columns, `user.address.city` reading field `city` of field `address` of
+/// column `user`. Names in the schema, such as flattened columns, are kept.
+pub(crate) fn resolve_fields(expr: Expr, schema: &Schema) -> Expr {
+    let field = |name: &str| -> Option<Expr> {
+        if schema.contains(name) || !name.contains('.') {
+            return None;
+        }
+        let parts: Vec<_> = name.split('.').collect();
+        (1..parts.len()).rev().find_map(|i| {
+            let column = parts[..i].join(".");
+            schema.contains(&column).then(|| {
+                parts[i..].iter().fold(col(&column), |expr, part| {
+                    expr.struct_().field_by_name(part)
+                })
+            })
+        })
+    };
+
+    // a selected field keeps its dotted name
+    if let Expr::Column(name) = &expr {
+        return match field(name) {
+            Some(resolved) => resolved.alias(name),
+            None => expr,
+        };
+    }
+    expr.map_expr(|e| match &e {
+        Expr::Column(name) => field(name).unwrap_or(e),
+        _ => e,
+    })
+}
+
 impl TryFrom<Operation> for Operator {
     type Error = anyhow::Error;
 
@@ -119,6 +288,14 @@ impl<'a> TryFrom<Projection<'a>> for Expr {
                 "max" => Ok(col(&f.args[0].to_string()).max().alias(&alias.to_string())),
                 "min" => Ok(col(&f.args[0].to_string()).min().alias(&alias.to_string())),
                 "mean" | "avg" => Ok(col(&f.args[0].to_string()).mean().alias(&alias.to_string())),
+                "unnest" | "explode" => Ok(col(&f.args[0].to_string())
+                    .explode()
+                    .alias(&alias.to_string())),
+                "json_extract" | "json_extract_string" => {
+                    let expr: Expr =
+                        Expression(Box::new(SqlExpr::Function(f.clone()))).try_into()?;
+                    Ok(expr.alias(&alias.to_string()))
+                }
                 unknown => Err(anyhow!("function {} not support yet", unknown)),
             },
             SelectItem::UnnamedExpr(SqlExpr::Function(f)) => {
@@ -137,15 +314,181 @@ impl<'a> TryFrom<Projection<'a>> for Expr {
                     "max" => Ok(col(&f.args[0].to_string()).max()),
                     "min" => Ok(col(&f.args[0].to_string()).min()),
                     "mean" | "avg" => Ok(col(&f.args[0].to_string()).mean()),
+                    "unnest" | "explode" => Ok(col(&f.args[0].to_string()).explode()),
+                    "json_extract" | "json_extract_string" => {
+                        Expression(Box::new(SqlExpr::Function(f.clone()))).try_into()
+                    }
                     unknown => Err(anyhow!("function {} not support yet", unknown)),
                 }
             }
-            item => Err(anyhow!("Projection {} not supported", item)),
+            SelectItem::UnnamedExpr(expr) => Expression(Box::new(expr.clone())).try_into(),
+            SelectItem::ExprWithAlias { expr, alias } => {
+                let expr: Expr = Expression(Box::new(expr.clone())).try_into()?;
+                Ok(expr.alias(&alias.value))
+            }
+        }
+    }
+}
+
+impl Sql<'_> {
+    /// The source columns the query reads, `None` when it reads all of them.
+    pub fn columns(&self) -> Option<BTreeSet<String>> {
+        // after an aggregation the projection and ordering name its output
+        let mut exprs: Vec<&Expr> = self.condition.iter().collect();
+        if self.aggregation.is_empty() {
+            exprs.extend(&self.selection);
+        } else {
+            exprs.extend(&self.group_by);
+            exprs.extend(&self.aggregation);
+        }
+
+        let mut columns = BTreeSet::new();
+        for expr in exprs {
+            for e in expr {
+                match e {
+                    Expr::Wildcard => return None,
+                    // a field of a struct column needs the column
+                    Expr::Column(name) => {
+                        let column = name.split('.').next().unwrap_or(name);
+                        columns.insert(column.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if self.aggregation.is_empty() {
+            columns.extend(self.order_by.iter().map(|(name, _)| name.clone()));
+        }
+        Some(columns)
+    }
+}
+
+impl fmt::Display for DataSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataSource::Uri(uri) => write!(f, "{}", uri),
+            DataSource::Values(df) => write!(f, "VALUES ({} rows)", df.height()),
+            DataSource::Function(func) => {
+                let args = func.args.iter().map(|v| v.to_string()).chain(
+                    func.named_args
+                        .iter()
+                        .map(|(k, v)| format!("{} => {}", k, v)),
+                );
+                write!(f, "{}({})", func.name, args.collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+impl TableFunction {
+    /// The argument at `index`, or named `name`.
+    pub fn arg(&self, index: usize, name: &str) -> Option<&SqlValue> {
+        self.named_args
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+            .or_else(|| self.args.get(index))
+    }
+
+    /// A string argument, at `index` or named `name`.
+    pub fn string(&self, index: usize, name: &str) -> Result<Option<String>> {
+        match self.arg(index, name) {
+            None => Ok(None),
+            Some(SqlValue::SingleQuotedString(s)) | Some(SqlValue::DoubleQuotedString(s)) => {
+                Ok(Some(s.clone()))
+            }
+            Some(v) => Err(anyhow!(
+                "{}: expected a string for {}, got {}",
+                self.name,
+                name,
+                v
+            )),
+        }
+    }
+}
+
+impl TableFunction {
+    /// A boolean argument named `name`.
+    pub fn boolean(&self, name: &str) -> Result<Option<bool>> {
+        match self.arg(usize::MAX, name) {
+            None => Ok(None),
+            Some(SqlValue::Boolean(b)) => Ok(Some(*b)),
+            Some(v) => Err(anyhow!(
+                "{}: expected true or false for {}, got {}",
+                self.name,
+                name,
+                v
+            )),
+        }
+    }
+
+    /// A non-negative integer argument named `name`.
+    pub fn integer(&self, name: &str) -> Result<Option<usize>> {
+        match self.arg(usize::MAX, name) {
+            None => Ok(None),
+            Some(SqlValue::Number(n, _)) if n.parse::<usize>().is_ok() => Ok(n.parse().ok()),
+            Some(v) => Err(anyhow!(
+                "{}: expected a non-negative integer for {}, got {}",
+                self.name,
+                name,
+                v
+            )),
+        }
+    }
+
+    /// Fail on more than `positional` unnamed arguments or on named
+    /// arguments not in `named`.
+    pub fn check(&self, positional: usize, named: &[&str]) -> Result<()> {
+        if self.args.len() > positional {
+            return Err(anyhow!(
+                "{} takes at most {} unnamed arguments, got {}",
+                self.name,
+                positional,
+                self.args.len()
+            ));
+        }
+        match self
+            .named_args
+            .iter()
+            .find(|(k, _)| !named.iter().any(|n| n.eq_ignore_ascii_case(k)))
+        {
+            Some((k, _)) => Err(anyhow!(
+                "{} has no option {}, expected one of {}",
+                self.name,
+                k,
+                named.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [FunctionArg]> for TableFunction {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &'a [FunctionArg]) -> Result<Self, Self::Error> {
+        let mut func = TableFunction {
+            name: String::new(),
+            args: Vec::new(),
+            named_args: Vec::new(),
+        };
+        for arg in args {
+            match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(SqlExpr::Value(v))) => {
+                    func.args.push(v.clone())
+                }
+                FunctionArg::Named {
+                    name,
+                    arg: FunctionArgExpr::Expr(SqlExpr::Value(v)),
+                } => func.named_args.push((name.value.clone(), v.clone())),
+                arg => return Err(anyhow!("Table function argument {} must be a literal", arg)),
+            }
         }
+        Ok(func)
     }
 }
 
-impl<'a> TryFrom<Source<'a>> for &'a str {
+impl<'a> TryFrom<Source<'a>> for DataSource<'a> {
     type Error = anyhow::Error;
 
     fn try_from(source: Source<'a>) -> Result<Self, Self::Error> {
@@ -159,12 +502,93 @@ impl<'a> TryFrom<Source<'a>> for &'a str {
         }
 
         match &table.relation {
-            TableFactor::Table { name, .. } => Ok(&name.0.first().unwrap().value),
+            TableFactor::Table {
+                name, args: None, ..
+            } => Ok(DataSource::Uri(&name.0.first().unwrap().value)),
+            TableFactor::Table {
+                name,
+                args: Some(args),
+                ..
+            } => {
+                let mut func = TableFunction::try_from(args.as_slice())?;
+                func.name = name.to_string().to_lowercase();
+                Ok(DataSource::Function(func))
+            }
+            TableFactor::Derived {
+                subquery, alias, ..
+            } => match subquery.body.as_ref() {
+                SetExpr::Values(values) => Ok(DataSource::Values(
+                    Rows(&values.rows, alias.as_ref()).try_into()?,
+                )),
+                _ => Err(anyhow!("We only support VALUES subqueries")),
+            },
             _ => Err(anyhow!("We only support table")),
         }
     }
 }
 
+impl<'a> TryFrom<Rows<'a>> for DataFrame {
+    type Error = anyhow::Error;
+
+    fn try_from(rows: Rows<'a>) -> Result<Self, Self::Error> {
+        let Rows(rows, alias) = rows;
+        let width = rows.first().map_or(0, Vec::len);
+        let names: Vec<String> = match alias.map(|a| a.columns.as_slice()) {
+            Some(columns) if !columns.is_empty() => columns.iter().map(Ident::to_string).collect(),
+            // the names PostgreSQL gives unnamed VALUES columns
+            _ => (1..=width).map(|i| format!("column{}", i)).collect(),
+        };
+        if names.len() != width {
+            return Err(anyhow!(
+                "VALUES has {} columns but {} names",
+                width,
+                names.len()
+            ));
+        }
+
+        let mut columns = vec![Vec::with_capacity(rows.len()); width];
+        for row in rows {
+            if row.len() != width {
+                return Err(anyhow!("Every VALUES row must have {} values", width));
+            }
+            for (column, expr) in columns.iter_mut().zip(row) {
+                column.push(any_value(expr)?);
+            }
+        }
+
+        let series = names
+            .iter()
+            .zip(columns)
+            .map(|(name, values)| Series::from_any_values(name, &values, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(DataFrame::new(series)?)
+    }
+}
+
+fn any_value(expr: &SqlExpr) -> Result<AnyValue<'static>> {
+    match expr {
+        SqlExpr::Value(SqlValue::Number(n, _)) => match n.parse::<i64>() {
+            std::result::Result::Ok(n) => Ok(AnyValue::Int64(n)),
+            Err(_) => Ok(AnyValue::Float64(n.parse()?)),
+        },
+        SqlExpr::Value(SqlValue::SingleQuotedString(s))
+        | SqlExpr::Value(SqlValue::DoubleQuotedString(s)) => {
+            Ok(AnyValue::StringOwned(s.as_str().into()))
+        }
+        SqlExpr::Value(SqlValue::Boolean(b)) => Ok(AnyValue::Boolean(*b)),
+        SqlExpr::Value(SqlValue::Null) => Ok(AnyValue::Null),
+        SqlExpr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match any_value(expr)? {
+            AnyValue::Int64(n) => Ok(AnyValue::Int64(-n)),
+            AnyValue::Float64(n) => Ok(AnyValue::Float64(-n)),
+            _ => Err(anyhow!("Can not negate {}", expr)),
+        },
+        expr => Err(anyhow!("VALUES only supports literals, got {}", expr)),
+    }
+}
+
 impl<'a> TryFrom<Order<'a>> for (String, bool) {
     type Error = anyhow::Error;
 
@@ -217,6 +641,21 @@ impl TryFrom<Value> for LiteralValue {
     }
 }
 
+/// The column of an `UNNEST(col)` projection, possibly aliased.
+fn exploded(expr: &Expr) -> Option<String> {
+    let expr = match expr {
+        Expr::Alias(inner, _) => inner.as_ref(),
+        expr => expr,
+    };
+    match expr {
+        Expr::Explode(inner) => match inner.as_ref() {
+            Expr::Column(name) => Some(name.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 impl<'a> TryFrom<&'a Statement> for Sql<'a> {
     type Error = anyhow::Error;
 
@@ -250,9 +689,17 @@ impl<'a> TryFrom<&'a Statement> for Sql<'a> {
 
                 let mut selection = Vec::with_capacity(8);
                 let mut aggregation = Vec::with_capacity(8);
+                let mut unnest = Vec::new();
 
                 for p in projection {
-                    let expr = Projection(p).try_into()?;
+                    let mut expr = Projection(p).try_into()?;
+                    if let Some(column) = exploded(&expr) {
+                        unnest.push(column.clone());
+                        expr = match expr {
+                            Expr::Alias(_, alias) => col(&column).alias(&alias),
+                            _ => col(&column),
+                        };
+                    }
                     match &expr {
                         Expr::Alias(x, y) => selection.push(x.as_ref().clone().alias(y)),
                         Expr::Wildcard => selection.push(expr),
@@ -312,6 +759,13 @@ impl<'a> TryFrom<&'a Statement> for Sql<'a> {
                             aggregation.push(len().alias("count"));
                             selection.push(col("count"));
                         }
+                        // row-wise expressions such as field access
+                        _ if !expr
+                            .into_iter()
+                            .any(|e| matches!(e, Expr::Agg(_) | Expr::Len)) =>
+                        {
+                            selection.push(expr)
+                        }
                         _ => return Err(anyhow!("Unsupport projection type: {}", expr)),
                     }
                 }
@@ -327,6 +781,7 @@ impl<'a> TryFrom<&'a Statement> for Sql<'a> {
                 Ok(Sql {
                     selection,
                     source,
+                    unnest,
                     condition,
                     group_by,
                     aggregation,
@@ -357,11 +812,30 @@ mod tests {
         let statement = &Parser::parse_sql(&XQDialect, sql.as_ref()).unwrap()[0];
         let sql: Sql = statement.try_into().unwrap();
 
-        assert_eq!(sql.source, url);
+        assert_eq!(sql.source, DataSource::Uri(url));
         assert_eq!(sql.limit, Some(5));
         assert_eq!(sql.offset, Some(10));
         assert_eq!(sql.order_by, vec![("c".into(), true)]);
         assert_eq!(sql.selection, vec![col("a"), col("b"), col("c")]);
         assert_eq!(sql.group_by, vec![col("c")]);
     }
+
+    #[test]
+    fn parse_table_function() {
+        let sql = "select * from sqlite('/tmp/app.db', table => 'events')";
+        let statement = &Parser::parse_sql(&XQDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+
+        let DataSource::Function(func) = &sql.source else {
+            panic!("expected a table function, got {}", sql.source);
+        };
+        assert_eq!(func.name, "sqlite");
+        assert_eq!(func.string(0, "path").unwrap().unwrap(), "/tmp/app.db");
+        assert_eq!(func.string(1, "table").unwrap().unwrap(), "events");
+        assert!(func.string(2, "query").unwrap().is_none());
+        assert_eq!(
+            sql.source.to_string(),
+            "sqlite('/tmp/app.db', table => 'events')"
+        );
+    }
 }
@@ -1,4 +1,7 @@
+use sqlparser::ast::{Expr, Value};
 use sqlparser::dialect::Dialect;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::Token;
 
 #[derive(Debug, Default)]
 pub struct XQDialect;
@@ -12,7 +15,38 @@ impl Dialect for XQDialect {
         ch.is_ascii_lowercase()
             || ch.is_ascii_uppercase()
             || ch.is_ascii_digit()
-            || [':', '/', '?', '&', '=', '-', '_', '.'].contains(&ch)
+            || [':', '/', '?', '&', '=', '-', '_', '.', '#', '@'].contains(&ch)
+    }
+
+    /// Read a struct literal such as `{id: 'BIGINT', ts: 'TIMESTAMP'}` given
+    /// to a named argument, e.g. the `columns` of a table function, as the
+    /// string of its tokens. Braces anywhere else are left to the parser,
+    /// which rejects them.
+    fn parse_prefix(&self, parser: &mut Parser) -> Option<Result<Expr, ParserError>> {
+        if parser.peek_token().token != Token::LBrace {
+            return None;
+        }
+        parser.prev_token();
+        if parser.next_token().token != Token::RArrow {
+            return None;
+        }
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = parser.next_token();
+            match token.token {
+                Token::LBrace => depth += 1,
+                Token::RBrace => depth -= 1,
+                Token::EOF => return Some(parser.expected("}", token)),
+                _ => {}
+            }
+            tokens.push(token.token.to_string());
+            if depth == 0 {
+                break;
+            }
+        }
+        let text = tokens.join(" ");
+        Some(Ok(Expr::Value(Value::SingleQuotedString(text))))
     }
 }
 
@@ -36,4 +70,27 @@ mod tests {
     fn it_works() {
         assert!(Parser::parse_sql(&XQDialect, &example_sql()).is_ok())
     }
+
+    #[test]
+    fn zip_member_fragment() {
+        let sql = "SELECT * FROM file:///tmp/a.zip#inner.csv";
+        let ast = Parser::parse_sql(&XQDialect, sql).unwrap();
+        assert_eq!(ast[0].to_string(), sql);
+    }
+
+    #[test]
+    fn struct_literal() {
+        let sql = "SELECT * FROM read_csv('a.csv', columns => {id: 'BIGINT', ts: 'DATE'})";
+        let ast = Parser::parse_sql(&XQDialect, sql).unwrap();
+        assert!(ast[0]
+            .to_string()
+            .contains("columns => '{ id: ''BIGINT'' , ts: ''DATE'' }'"));
+
+        for sql in [
+            "SELECT {a: 1} FROM read_csv('a.csv')",
+            "SELECT * FROM read_csv('a.csv') WHERE a = {b: 1}",
+        ] {
+            assert!(Parser::parse_sql(&XQDialect, sql).is_err(), "{}", sql);
+        }
+    }
 }
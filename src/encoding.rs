@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::loader::magic_format;
+
+/// The `charset` parameter of a media type such as `text/csv; charset=gbk`.
+pub fn charset(media_type: &str) -> Option<&str> {
+    media_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        name.trim().eq_ignore_ascii_case("charset").then_some(value)
+    })
+}
+
+/// Transcode text `data` read from `location` to UTF-8. A byte order mark
+/// tells its encoding, else `label`, e.g. `gbk`, `big5` or `utf-16le`.
+/// UTF-8 is left as is, without its byte order mark, and binary formats
+/// such as Parquet are never transcoded.
+pub fn transcode(location: &str, data: Vec<u8>, label: Option<&str>) -> Result<Vec<u8>> {
+    if magic_format(&data).is_some() {
+        return Ok(data);
+    }
+    let (encoding, bom) = match (Encoding::for_bom(&data), label) {
+        (Some((encoding, bom)), _) => (encoding, bom),
+        (None, Some(label)) => {
+            let encoding = Encoding::for_label(label.trim().as_bytes())
+                .ok_or_else(|| anyhow!("Unknown encoding {}", label))?;
+            (encoding, 0)
+        }
+        (None, None) => return Ok(data),
+    };
+    if encoding == UTF_8 {
+        return Ok(match bom {
+            0 => data,
+            _ => data[bom..].to_vec(),
+        });
+    }
+
+    let (text, _, malformed) = encoding.decode(&data);
+    tracing::debug!("reading {} as {}", location, encoding.name());
+    if malformed {
+        tracing::warn!(
+            "{} is not valid {}, its invalid bytes were replaced by U+FFFD",
+            location,
+            encoding.name()
+        );
+    }
+    Ok(text.into_owned().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcoding() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("城市,人口\n北京,2189\n");
+        assert_eq!(
+            transcode("a.csv", gbk.to_vec(), Some("gbk")).unwrap(),
+            "城市,人口\n北京,2189\n".as_bytes()
+        );
+        assert_eq!(
+            transcode("a.csv", gbk.to_vec(), Some("GB18030")).unwrap(),
+            "城市,人口\n北京,2189\n".as_bytes()
+        );
+        assert!(transcode("a.csv", gbk.to_vec(), Some("ebcdic")).is_err());
+        assert_eq!(transcode("a.csv", gbk.to_vec(), None).unwrap(), *gbk);
+
+        // Windows exports: UTF-16 and UTF-8 with a byte order mark
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("a,b\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(transcode("a.csv", utf16, None).unwrap(), b"a,b\n");
+        let utf8 = b"\xef\xbb\xbfa,b\n".to_vec();
+        assert_eq!(transcode("a.csv", utf8, Some("big5")).unwrap(), b"a,b\n");
+
+        let parquet = b"PAR1\xff\xfe".to_vec();
+        assert_eq!(
+            transcode("a", parquet.clone(), Some("gbk")).unwrap(),
+            parquet
+        );
+    }
+
+    #[test]
+    fn media_type_charset() {
+        assert_eq!(charset("text/csv; charset=GBK"), Some("GBK"));
+        assert_eq!(charset("text/csv;charset=\"big5\""), Some("big5"));
+        assert_eq!(charset("text/csv"), None);
+    }
+}
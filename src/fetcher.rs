@@ -1,39 +1,910 @@
-use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use base64::Engine;
+use polars::prelude::{DataFrame, Expr};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE, LINK};
+use reqwest::Url;
+use serde_json::Value;
 use tokio::fs;
+use tokio::io::{stdin, AsyncReadExt};
+
+use crate::cache::Cache;
+use crate::compression::{decompress, Compression};
+use crate::encoding::{charset, transcode};
+use crate::http::{HttpOptions, Pagination, Response};
+use crate::loader::{is_spreadsheet, select, Format};
+use crate::partition::{self, Partitions};
+use crate::QueryOptions;
+
+const DEFAULT_MAX_PAGES: usize = 100;
 
 #[async_trait]
 pub trait Fetch {
     type Error;
-    async fn fetch(&self) -> Result<String, Self::Error>;
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Data read from a single location of a source.
+#[derive(Debug)]
+pub struct Content {
+    /// Where the data was read from.
+    pub location: String,
+    /// Hive-style `key=value` partitions found in the location.
+    pub partitions: Partitions,
+    /// Format declared by the source, e.g. the media type of a `data:` URI.
+    pub format: Option<Format>,
+    /// Part of the data named by the fragment: the sheet of a spreadsheet,
+    /// e.g. `report.xlsx#Q3`, or the table of an HTML page, e.g.
+    /// `page.html#sales`.
+    pub fragment: Option<String>,
+    pub data: Data,
+}
+
+#[derive(Debug)]
+pub enum Data {
+    Bytes(Vec<u8>),
+    /// A local Parquet file, scanned lazily so only the row groups and
+    /// columns the query needs are read.
+    Parquet(PathBuf),
+    /// A remote Parquet file, already read with range requests.
+    Frame(DataFrame),
+}
+
+/// Read every location `source` refers to.
+///
+/// `-`, `stdin` and `stdin://` read the data piped into the process.
+///
+/// A `data:` URI holds the data itself, e.g. `data:text/csv,a,b%0A1,2` or
+/// `data:application/json;base64,...`.
+///
+/// Text is transcoded to UTF-8 from the encoding told by its byte order
+/// mark, else by the `encoding` of the options or the declared charset,
+/// e.g. `text/csv; charset=gbk`.
+///
+/// Compressed data is decompressed transparently. A zip archive member is
+/// picked with a fragment, e.g. `file:///a.zip#inner.csv`, and so are the
+/// sheet of a spreadsheet, e.g. `file:///report.xlsx#Sheet2`, and the table
+/// of an HTML page, e.g. `https://example.com/page.html#sales`. Other
+/// fragments are ignored, and `data:` URIs and existing files keep a `#`.
+///
+/// A `file://` source may be a glob pattern or a directory, in which case
+/// every matching file is read. Parquet files are not read up front but
+/// left for the loader to scan. Files in hive-style partition directories
+/// that can not satisfy `condition` are skipped without being read.
+pub async fn retrieve_data(
+    source: impl AsRef<str>,
+    condition: Option<&Expr>,
+    options: &QueryOptions,
+) -> Result<Vec<Content>> {
+    let source = source.as_ref();
+    let (name, member) = match source.split_once('#') {
+        _ if source.starts_with("data:") => (source, None),
+        _ if source.starts_with("file://") && Path::new(&source[7..]).is_file() => (source, None),
+        Some((name, member)) => (name, Some(member)),
+        None => (source, None),
+    };
+
+    let encoding = options.encoding.as_deref();
+    match name {
+        "-" | "stdin" | "stdin://" => {
+            let data = StdinFetcher.fetch().await?;
+            Ok(vec![Content::new(
+                "stdin",
+                Vec::new(),
+                data,
+                member,
+                encoding,
+            )?])
+        }
+        n if n.starts_with("data:") => {
+            let fetcher = DataFetcher(name);
+            let media_type = fetcher.split()?.0;
+            let encoding = encoding.or_else(|| charset(media_type));
+            let data = fetcher.fetch().await?;
+            let mut content = Content::new("data:", Vec::new(), data, member, encoding)?;
+            content.format = Format::from_media_type(media_type);
+            Ok(vec![content])
+        }
+        n if n.starts_with("http") => {
+            let fetcher = UrlFetcher {
+                url: name,
+                http: &options.http_for(name),
+                cache: options.cache.as_ref(),
+            };
+            let (data, media_type) = fetcher.fetch_typed().await?;
+            let media_type = media_type.as_deref().unwrap_or_default();
+            let encoding = encoding.or_else(|| charset(media_type));
+            let partitions = partition::from_url(name);
+            let mut content = Content::new(name, partitions, data, member, encoding)?;
+            content.format = content.format.or(Format::from_media_type(media_type));
+            Ok(vec![content])
+        }
+        n if n.starts_with("file://") => {
+            let files = list_files(&name[7..])?;
+            let partitions = partition::typed(&files);
+            let files = partition::prune(files.into_iter().zip(partitions).collect(), condition)?;
+            let mut contents = Vec::with_capacity(files.len());
+            for (file, partitions) in files {
+                let location = file.display().to_string();
+                if member.is_none() && is_parquet_file(&file, options.format) {
+                    contents.push(Content {
+                        location,
+                        partitions,
+                        format: Some(Format::Parquet),
+                        fragment: None,
+                        data: Data::Parquet(file),
+                    });
+                    continue;
+                }
+                let data = FileFetcher(&file).fetch().await?;
+                contents.push(Content::new(location, partitions, data, member, encoding)?);
+            }
+            Ok(contents)
+        }
+        _ => Err(anyhow!(
+            "We only support http/https/file/stdin/data at the moment"
+        )),
+    }
+}
+
+impl Content {
+    fn new(
+        location: impl Into<String>,
+        partitions: Partitions,
+        data: Vec<u8>,
+        member: Option<&str>,
+        encoding: Option<&str>,
+    ) -> Result<Self> {
+        let location = location.into();
+        // xlsx and ods are zip archives, read as a whole with the fragment
+        // naming a sheet
+        if Format::from_extension(&location) == Some(Format::Excel) || is_spreadsheet(&data) {
+            return Ok(Self {
+                location,
+                partitions,
+                format: Some(Format::Excel),
+                fragment: member.map(String::from),
+                data: Data::Bytes(data),
+            });
+        }
+        // the fragment names the member of a zip archive, else a part of
+        // the data
+        let archive = Compression::detect(&location, &data) == Some(Compression::Zip);
+        let data = decompress(&location, data, member)?;
+        let data = Data::Bytes(transcode(&location, data, encoding)?);
+        Ok(Self {
+            location,
+            partitions,
+            format: None,
+            fragment: member.filter(|_| !archive).map(String::from),
+            data,
+        })
+    }
 }
 
-pub async fn retrieve_data(source: impl AsRef<str>) -> Result<String> {
-    let name = source.as_ref();
+/// Whether `path` is a Parquet file, by its extension or magic bytes,
+/// unless another format was asked for.
+fn is_parquet_file(path: &Path, format: Option<Format>) -> bool {
+    match format {
+        Some(format) => format == Format::Parquet,
+        None => {
+            let mut magic = [0; 4];
+            Format::from_extension(&path.to_string_lossy()) == Some(Format::Parquet)
+                || std::fs::File::open(path)
+                    .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut magic))
+                    .is_ok_and(|_| &magic == b"PAR1")
+        }
+    }
+}
 
-    match &name[..4] {
-        "http" => UrlFetcher(name).fetch().await,
-        "file" => FileFetcher(name).fetch().await,
-        _ => Err(anyhow!("We only support http/https/file at the moment")),
+/// Expand a local path into the files it refers to: the files matching a
+/// glob pattern, every file below a directory, or the path itself.
+fn list_files(path: &str) -> Result<Vec<PathBuf>> {
+    let mut files = if path.contains(['*', '?', '[']) {
+        glob::glob(path)?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|p| p.is_file())
+            .collect()
+    } else if Path::new(path).is_dir() {
+        let mut files = Vec::new();
+        walk(Path::new(path), &mut files)?;
+        files
+    } else {
+        return Ok(vec![PathBuf::from(path)]);
+    };
+
+    if files.is_empty() {
+        return Err(anyhow!("No files found for {}", path));
     }
+    files.sort();
+    Ok(files)
 }
 
-struct UrlFetcher<'a>(&'a str);
-struct FileFetcher<'a>(&'a str);
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // skip hidden files and markers such as `_SUCCESS`
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name.starts_with(['.', '_']) {
+            continue;
+        }
+        // symlinked directories are not followed, they may form a loop
+        if entry.file_type()?.is_symlink() && path.is_dir() {
+            tracing::debug!("skipping symlinked directory {}", path.display());
+            continue;
+        }
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+struct UrlFetcher<'a> {
+    url: &'a str,
+    http: &'a HttpOptions,
+    cache: Option<&'a Cache>,
+}
+struct FileFetcher<'a>(&'a Path);
+struct StdinFetcher;
+struct DataFetcher<'a>(&'a str);
 
 #[async_trait]
 impl<'a> Fetch for UrlFetcher<'a> {
     type Error = anyhow::Error;
-    async fn fetch(&self) -> Result<String, Self::Error> {
-        Ok(reqwest::get(self.0).await?.text().await?)
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.fetch_typed().await?.0)
+    }
+}
+
+impl<'a> UrlFetcher<'a> {
+    /// The body with its `Content-Type`, if any.
+    async fn fetch_typed(&self) -> Result<(Vec<u8>, Option<String>)> {
+        match &self.http.pagination {
+            Some(pagination) => Ok((
+                self.fetch_pages(pagination).await?,
+                Some("application/json".to_string()),
+            )),
+            None => {
+                let resp = self.get(self.http, self.url).await?;
+                let media_type = resp
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(String::from);
+                Ok((resp.body, media_type))
+            }
+        }
+    }
+
+    /// Request `url` through the cache, decoding its `Content-Encoding`.
+    async fn get(&self, http: &HttpOptions, url: &str) -> Result<Response> {
+        let mut resp = match self.cache {
+            Some(cache) => cache.fetch(http, url).await?,
+            None => http.send(&http.client()?, url).await?,
+        };
+        let encoding = match resp.headers.get(CONTENT_ENCODING) {
+            Some(v) => Compression::from_content_encoding(v.to_str()?)?,
+            None => None,
+        };
+        if let Some(compression) = encoding {
+            resp.body = compression.decompress(&resp.body, None)?;
+        }
+        Ok(resp)
+    }
+
+    /// Request every page of a paginated JSON API, returning the records
+    /// of all pages as a single JSON array. Pages on another origin than
+    /// the first are requested without its credentials.
+    async fn fetch_pages(&self, pagination: &Pagination) -> Result<Vec<u8>> {
+        let max_pages = self.http.max_pages.unwrap_or(DEFAULT_MAX_PAGES);
+        let mut url = Url::parse(self.url)?;
+        let mut page = 0;
+        let mut offset = 0;
+        match pagination {
+            Pagination::Page { param, start } => {
+                page = *start;
+                url = with_param(&url, param, &page.to_string());
+            }
+            Pagination::Offset { param } => {
+                offset = query_param(&url, param)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                url = with_param(&url, param, &offset.to_string());
+            }
+            _ => {}
+        }
+
+        let origin = url.origin();
+        let foreign = self.http.without_credentials();
+        let mut records = Vec::new();
+        for _ in 0..max_pages {
+            tracing::debug!("requesting page {}", url);
+            let http = match url.origin() == origin {
+                true => self.http,
+                false => &foreign,
+            };
+            let resp = self.get(http, url.as_str()).await?;
+            let body: Value = serde_json::from_slice(&resp.body)
+                .with_context(|| format!("Page {} is not valid JSON", url))?;
+
+            let page_records = page_records(body.clone(), self.http.records_path.as_deref())
+                .with_context(|| format!("Page {}", url))?;
+            if page_records.is_empty() {
+                return Ok(serde_json::to_vec(&records)?);
+            }
+            let count = page_records.len();
+            records.extend(page_records);
+
+            let next = match pagination {
+                Pagination::Link => next_link(&resp.headers, &url),
+                Pagination::Cursor { param, cursor_path } => match select(&body, cursor_path) {
+                    Some(Value::String(c)) if c.starts_with("http") => Url::parse(c).ok(),
+                    Some(Value::String(c)) if !c.is_empty() => Some(with_param(&url, param, c)),
+                    Some(Value::Number(c)) => Some(with_param(&url, param, &c.to_string())),
+                    _ => None,
+                },
+                Pagination::Page { param, .. } => {
+                    page += 1;
+                    Some(with_param(&url, param, &page.to_string()))
+                }
+                Pagination::Offset { param } => {
+                    offset += count;
+                    Some(with_param(&url, param, &offset.to_string()))
+                }
+            };
+
+            match next {
+                Some(next) => url = next,
+                None => return Ok(serde_json::to_vec(&records)?),
+            }
+        }
+
+        tracing::warn!("stopped after {} pages of {}", max_pages, self.url);
+        Ok(serde_json::to_vec(&records)?)
+    }
+}
+
+/// The records of a page: the array at `path`, or the whole page.
+fn page_records(page: Value, path: Option<&str>) -> Result<Vec<Value>> {
+    let records = match path {
+        Some(path) => select(&page, path)
+            .cloned()
+            .ok_or_else(|| anyhow!("records_path {} not found in page", path))?,
+        None => page,
+    };
+    match records {
+        Value::Array(records) => Ok(records),
+        Value::Null => Ok(Vec::new()),
+        // a page never runs out of records, without a path to them
+        Value::Object(_) if path.is_none() => Err(anyhow!(
+            "Expected an array of records, got an object, use records_path"
+        )),
+        record @ Value::Object(_) => Ok(vec![record]),
+        v => Err(anyhow!("Expected records in page, got {}", v)),
     }
 }
 
+/// The `rel="next"` target of a `Link` header, resolved against `base`.
+fn next_link(headers: &HeaderMap, base: &Url) -> Option<Url> {
+    headers.get_all(LINK).iter().find_map(|value| {
+        value.to_str().ok()?.split(',').find_map(|link| {
+            let (target, params) = link.split_once(';')?;
+            let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+            params
+                .split(';')
+                .filter_map(|p| p.trim().strip_prefix("rel="))
+                .any(|rel| {
+                    rel.trim_matches('"')
+                        .split_whitespace()
+                        .any(|r| r == "next")
+                })
+                .then(|| base.join(target).ok())?
+        })
+    })
+}
+
+fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn with_param(url: &Url, name: &str, value: &str) -> Url {
+    let pairs: Vec<_> = url
+        .query_pairs()
+        .filter(|(k, _)| k != name)
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(name, value);
+    url
+}
+
 #[async_trait]
 impl<'a> Fetch for FileFetcher<'a> {
     type Error = anyhow::Error;
 
-    async fn fetch(&self) -> Result<String, Self::Error> {
-        Ok(fs::read_to_string(&self.0[7..]).await?)
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        Ok(fs::read(self.0).await?)
+    }
+}
+
+#[async_trait]
+impl Fetch for StdinFetcher {
+    type Error = anyhow::Error;
+
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut data = Vec::new();
+        stdin().read_to_end(&mut data).await?;
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl<'a> Fetch for DataFetcher<'a> {
+    type Error = anyhow::Error;
+
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        let (media_type, data) = self.split()?;
+        let data = percent_decode(data)?;
+        if !media_type
+            .split(';')
+            .any(|p| p.trim().eq_ignore_ascii_case("base64"))
+        {
+            return Ok(data);
+        }
+        // tolerate line breaks and missing padding
+        let data: Vec<u8> = data
+            .into_iter()
+            .filter(|b| !b.is_ascii_whitespace() && *b != b'=')
+            .collect();
+        base64::engine::general_purpose::STANDARD_NO_PAD
+            .decode(data)
+            .map_err(|e| anyhow!("Invalid base64 in data URI: {}", e))
+    }
+}
+
+impl DataFetcher<'_> {
+    /// The media type and the still encoded data.
+    fn split(&self) -> Result<(&str, &str)> {
+        self.0
+            .strip_prefix("data:")
+            .and_then(|uri| uri.split_once(','))
+            .ok_or_else(|| {
+                anyhow!("Invalid data URI, expected data:[<media type>][;base64],<data>")
+            })
+    }
+}
+
+fn percent_decode(data: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut rest = data.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b != b'%' {
+            bytes.push(b);
+            rest = tail;
+            continue;
+        }
+        let byte = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| anyhow!("Invalid percent encoding in data URI"))?;
+        bytes.push(byte);
+        rest = &tail[2..];
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Auth;
+    use std::time::Duration;
+    use wiremock::matchers::{
+        body_string, header, header_exists, method, path, query_param as param,
+    };
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn text(content: &Content) -> &str {
+        match &content.data {
+            Data::Bytes(data) => std::str::from_utf8(data).unwrap(),
+            data => panic!("expected bytes, got {:?}", data),
+        }
+    }
+
+    #[tokio::test]
+    async fn data_uri() {
+        let options = QueryOptions::default();
+        let contents = retrieve_data("data:text/csv,a,b%0A1,2", None, &options)
+            .await
+            .unwrap();
+        assert_eq!(text(&contents[0]), "a,b\n1,2");
+        assert_eq!(contents[0].format, Some(Format::Csv));
+
+        // "[{\"a\": 1}]"
+        let uri = "data:application/json;base64,W3siYSI6IDF9XQ";
+        let contents = retrieve_data(uri, None, &options).await.unwrap();
+        assert_eq!(text(&contents[0]), "[{\"a\": 1}]");
+        assert_eq!(contents[0].format, Some(Format::Json));
+
+        assert!(retrieve_data("data:text/csv", None, &options)
+            .await
+            .is_err());
+        assert!(retrieve_data("data:,%zz", None, &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn literal_hashes() {
+        let options = QueryOptions::default();
+        let contents = retrieve_data("data:text/csv,a,b%0A1,#2", None, &options)
+            .await
+            .unwrap();
+        assert_eq!(text(&contents[0]), "a,b\n1,#2");
+
+        let file = std::env::temp_dir().join("xq#hash.csv");
+        std::fs::write(&file, "a\n1\n").unwrap();
+        let contents = retrieve_data(format!("file://{}", file.display()), None, &options)
+            .await
+            .unwrap();
+        assert_eq!(text(&contents[0]), "a\n1\n");
+        assert_eq!(contents[0].fragment, None);
+        std::fs::remove_file(file).ok();
+    }
+
+    #[test]
+    fn fragments() {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("xl/workbook.xml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"<workbook/>").unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        // a zip holding a workbook is not unpacked, the fragment is a sheet
+        let content = Content::new("report", Vec::new(), data.clone(), Some("Q3"), None).unwrap();
+        assert_eq!(content.format, Some(Format::Excel));
+        assert_eq!(content.fragment.as_deref(), Some("Q3"));
+        assert!(matches!(content.data, Data::Bytes(bytes) if bytes == data));
+
+        // other data keeps the fragment, e.g. for an HTML table
+        let content = Content::new(
+            "page.html",
+            Vec::new(),
+            b"<table/>".to_vec(),
+            Some("t"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(content.fragment.as_deref(), Some("t"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops() {
+        let dir = std::env::temp_dir().join("xq_symlink_loop");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/a.csv"), "a\n1\n").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        let files = list_files(&dir.display().to_string()).unwrap();
+        assert_eq!(files, [dir.join("sub/a.csv")]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn http_request_options() {
+        std::env::set_var("XQ_TEST_API_TOKEN", "t0ken");
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/items"))
+            .and(header("authorization", "Bearer t0ken"))
+            .and(header("x-api-key", "abc"))
+            .and(header("user-agent", "xq-test"))
+            .and(body_string(r#"{"page":1}"#))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a,b\n1,2"))
+            .mount(&server)
+            .await;
+
+        let http = HttpOptions::default()
+            .with_method("post")
+            .with_header("X-Api-Key", "abc")
+            .with_auth(Auth::Bearer("${XQ_TEST_API_TOKEN}".into()))
+            .with_user_agent("xq-test")
+            .with_body(r#"{"page":1}"#);
+        let options = QueryOptions::default().with_http(http);
+
+        let url = format!("{}/items", server.uri());
+        let contents = retrieve_data(&url, None, &options).await.unwrap();
+        assert_eq!(text(&contents[0]), "a,b\n1,2");
+        assert_eq!(contents[0].format, None);
+    }
+
+    #[tokio::test]
+    async fn http_content_type() {
+        let server = MockServer::start().await;
+        Mock::given(path("/export"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("[1,2]", "text/csv; charset=utf-8"),
+            )
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/export", server.uri());
+        let contents = retrieve_data(&url, None, &QueryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(contents[0].format, Some(Format::Csv));
+    }
+
+    #[tokio::test]
+    async fn http_charset() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("城市\n北京\n");
+        let server = MockServer::start().await;
+        Mock::given(path("/cities"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(gbk, "text/csv; charset=GBK"))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/cities", server.uri());
+        let contents = retrieve_data(&url, None, &QueryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(text(&contents[0]), "城市\n北京\n");
+    }
+
+    #[tokio::test]
+    async fn http_status_errors() {
+        let server = MockServer::start().await;
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("<html>Not Found</html>"))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/missing", server.uri());
+        let err = retrieve_data(&url, None, &QueryOptions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("404"), "{}", err);
+        assert!(err.contains("<html>Not Found</html>"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn http_retries() {
+        let server = MockServer::start().await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(429))
+            .up_to_n_times(1)
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a\n1"))
+            .with_priority(3)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/flaky", server.uri());
+        let http = HttpOptions::default().with_retry_backoff(Duration::from_millis(10));
+        let options = QueryOptions::default().with_http(http.clone());
+        let contents = retrieve_data(&url, None, &options).await.unwrap();
+        assert_eq!(text(&contents[0]), "a\n1");
+
+        server.reset().await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let options = QueryOptions::default().with_http(http.clone().with_retries(1));
+        assert!(retrieve_data(&url, None, &options).await.is_err());
+
+        // a POST is only retried when asked for
+        server.reset().await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let options = QueryOptions::default().with_http(http.with_method("POST"));
+        assert!(retrieve_data(&url, None, &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn http_limits() {
+        let server = MockServer::start().await;
+        Mock::given(path("/large"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a\n".repeat(100)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/large", server.uri());
+        let http = HttpOptions::default().with_max_response_size(100);
+        let options = QueryOptions::default().with_http(http);
+        let err = retrieve_data(&url, None, &options).await.unwrap_err();
+        assert!(err.to_string().contains("maximum size"), "{}", err);
+
+        let url = format!("{}/slow", server.uri());
+        let http = HttpOptions::default()
+            .with_read_timeout(Duration::from_millis(100))
+            .with_retries(0);
+        let options = QueryOptions::default().with_http(http);
+        let err = retrieve_data(&url, None, &options).await.unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn http_pagination() {
+        let server = MockServer::start().await;
+        let uri = server.uri();
+
+        // Link header
+        Mock::given(path("/link"))
+            .and(param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"[{"id": 3}]"#))
+            .mount(&server)
+            .await;
+        Mock::given(path("/link"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "Link",
+                        r#"</link?page=2>; rel="next", </link?page=2>; rel="last""#,
+                    )
+                    .set_body_string(r#"[{"id": 1}, {"id": 2}]"#),
+            )
+            .mount(&server)
+            .await;
+
+        // cursor in the body
+        Mock::given(path("/cursor"))
+            .and(param("after", "c1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"data": [{"id": 2}], "meta": {"next": null}}"#),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(path("/cursor"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"data": [{"id": 1}], "meta": {"next": "c1"}}"#),
+            )
+            .mount(&server)
+            .await;
+
+        // page numbers, ending with an empty page
+        for (page, body) in [
+            ("1", r#"[{"id": 1}]"#),
+            ("2", r#"[{"id": 2}]"#),
+            ("3", "[]"),
+        ] {
+            Mock::given(path("/page"))
+                .and(param("p", page))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        // offsets
+        for (offset, body) in [("0", r#"[{"id": 1}, {"id": 2}]"#), ("2", "[]")] {
+            Mock::given(path("/offset"))
+                .and(param("offset", offset))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let cases = [
+            (
+                "/link",
+                HttpOptions::default().with_pagination(Pagination::Link),
+                3,
+            ),
+            (
+                "/cursor",
+                HttpOptions::default()
+                    .with_pagination("cursor:after:meta.next".parse().unwrap())
+                    .with_records_path("data"),
+                2,
+            ),
+            (
+                "/page",
+                HttpOptions::default().with_pagination("page:p".parse().unwrap()),
+                2,
+            ),
+            (
+                "/page",
+                HttpOptions::default()
+                    .with_pagination("page:p".parse().unwrap())
+                    .with_max_pages(1),
+                1,
+            ),
+            (
+                "/offset?limit=2",
+                HttpOptions::default().with_pagination("offset:offset".parse().unwrap()),
+                2,
+            ),
+        ];
+        for (url, http, count) in cases {
+            let options = QueryOptions::default().with_http(http);
+            let contents = retrieve_data(format!("{}{}", uri, url), None, &options)
+                .await
+                .unwrap();
+            let records: Vec<Value> = serde_json::from_str(text(&contents[0])).unwrap();
+            assert_eq!(records.len(), count, "{}", url);
+        }
+
+        // an object page without records_path would be requested max_pages times
+        Mock::given(path("/object"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": []}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let options = QueryOptions::default()
+            .with_http(HttpOptions::default().with_pagination("page:p".parse().unwrap()));
+        let err = retrieve_data(format!("{}/object", uri), None, &options)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("records_path"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn pagination_across_origins() {
+        let server = MockServer::start().await;
+        let other = MockServer::start().await;
+        Mock::given(path("/first"))
+            .and(header("authorization", "Bearer s3cr3t"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Link", format!(r#"<{}/second>; rel="next""#, other.uri()))
+                    .set_body_string(r#"[{"id": 1}]"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/second"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(0)
+            .mount(&other)
+            .await;
+        Mock::given(path("/second"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"[{"id": 2}]"#))
+            .expect(1)
+            .mount(&other)
+            .await;
+
+        let http = HttpOptions::default()
+            .with_pagination(Pagination::Link)
+            .with_auth(Auth::Bearer("s3cr3t".into()));
+        let options = QueryOptions::default().with_http(http);
+        let contents = retrieve_data(format!("{}/first", server.uri()), None, &options)
+            .await
+            .unwrap();
+        let records: Vec<Value> = serde_json::from_str(text(&contents[0])).unwrap();
+        assert_eq!(records.len(), 2);
     }
 }
@@ -0,0 +1,546 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{
+    HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, RETRY_AFTER,
+};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Deserializer};
+
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest delay a `Retry-After` header may ask for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Longest delay between retries the backoff grows to.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Most bytes of an error response read for its message.
+const MAX_ERROR_BODY: usize = 4096;
+/// Headers carrying credentials.
+const CREDENTIAL_HEADERS: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+
+/// How requests to an HTTP source are made.
+///
+/// Every value may reference environment variables as `${NAME}`, which are
+/// expanded when the request is sent so secrets stay out of the SQL text
+/// and the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpOptions {
+    /// Request method, `GET` when unset.
+    pub method: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub auth: Option<Auth>,
+    pub body: Option<String>,
+    pub user_agent: Option<String>,
+    /// Time allowed to establish a connection, in seconds in the config file.
+    #[serde(default, deserialize_with = "seconds")]
+    pub connect_timeout: Option<Duration>,
+    /// Time allowed waiting for the response and between pieces of its body.
+    #[serde(default, deserialize_with = "seconds")]
+    pub read_timeout: Option<Duration>,
+    /// Times a `429`, `5xx` or failed connection is retried. When unset, 3
+    /// for GET, HEAD, PUT and DELETE requests, and none for other methods
+    /// such as POST, which may not be safe to repeat.
+    pub retries: Option<u32>,
+    /// Delay before the first retry, doubled for every further one, 500ms
+    /// when unset. A `Retry-After` header takes precedence, up to a minute.
+    #[serde(default, deserialize_with = "seconds")]
+    pub retry_backoff: Option<Duration>,
+    /// Largest response body accepted, in bytes.
+    pub max_response_size: Option<u64>,
+    /// How further pages of a paginated JSON API are requested.
+    pub pagination: Option<Pagination>,
+    /// Dot separated path of the records array in each page, e.g. `data.items`.
+    pub records_path: Option<String>,
+    /// Most pages requested from a paginated API, 100 when unset.
+    pub max_pages: Option<usize>,
+}
+
+/// Pagination schemes of JSON APIs. Requesting pages stops at the first
+/// page without records, or when there is no next page.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Pagination {
+    /// Follow the `Link: <url>; rel="next"` header.
+    Link,
+    /// Send the value at `cursor_path` in each page as the `param` query
+    /// parameter of the next request. A cursor that is a URL is requested
+    /// as is.
+    Cursor { param: String, cursor_path: String },
+    /// Count the `param` query parameter up from `start`.
+    Page {
+        param: String,
+        #[serde(default = "first_page")]
+        start: u64,
+    },
+    /// Advance the `param` query parameter by the records received.
+    Offset { param: String },
+}
+
+fn first_page() -> u64 {
+    1
+}
+
+/// A successful, or `304 Not Modified`, response with its body read.
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    Bearer(String),
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+}
+
+impl HttpOptions {
+    pub fn with_method(mut self, method: impl Into<String>) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Whether requests carry credentials, an `auth` or an `Authorization`
+    /// or `Cookie` header.
+    pub(crate) fn has_credentials(&self) -> bool {
+        self.auth.is_some() || self.headers.keys().any(|name| is_credential(name))
+    }
+
+    /// These options without `auth` and credential headers, for requests to
+    /// another origin.
+    pub(crate) fn without_credentials(&self) -> HttpOptions {
+        let mut http = self.clone();
+        http.auth = None;
+        http.headers.retain(|name, _| !is_credential(name));
+        http
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = Some(backoff);
+        self
+    }
+
+    pub fn with_max_response_size(mut self, size: u64) -> Self {
+        self.max_response_size = Some(size);
+        self
+    }
+
+    pub fn with_pagination(mut self, pagination: Pagination) -> Self {
+        self.pagination = Some(pagination);
+        self
+    }
+
+    pub fn with_records_path(mut self, path: impl Into<String>) -> Self {
+        self.records_path = Some(path.into());
+        self
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Overlay the options set in `other` on top of these.
+    pub(crate) fn merge(mut self, other: &HttpOptions) -> Self {
+        self.headers
+            .extend(other.headers.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.method = other.method.clone().or(self.method);
+        self.auth = other.auth.clone().or(self.auth);
+        self.body = other.body.clone().or(self.body);
+        self.user_agent = other.user_agent.clone().or(self.user_agent);
+        self.connect_timeout = other.connect_timeout.or(self.connect_timeout);
+        self.read_timeout = other.read_timeout.or(self.read_timeout);
+        self.retries = other.retries.or(self.retries);
+        self.retry_backoff = other.retry_backoff.or(self.retry_backoff);
+        self.max_response_size = other.max_response_size.or(self.max_response_size);
+        self.pagination = other.pagination.clone().or(self.pagination);
+        self.records_path = other.records_path.clone().or(self.records_path);
+        self.max_pages = other.max_pages.or(self.max_pages);
+        self
+    }
+
+    pub(crate) fn client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Send the request for `url` and read the response body.
+    ///
+    /// `429` and `5xx` responses and failed connections are retried with
+    /// exponential backoff, any other non-2xx status is an error.
+    pub(crate) async fn send(&self, client: &Client, url: &str) -> Result<Response> {
+        self.send_with(client, url, HeaderMap::new()).await
+    }
+
+    /// Like [`send`](Self::send), adding `headers` to the request.
+    pub(crate) async fn send_with(
+        &self,
+        client: &Client,
+        url: &str,
+        headers: HeaderMap,
+    ) -> Result<Response> {
+        let idempotent = matches!(
+            self.method()?,
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+        );
+        let retries = match (self.retries, idempotent) {
+            (Some(retries), _) => retries,
+            (None, true) => DEFAULT_RETRIES,
+            (None, false) => 0,
+        };
+        let mut backoff = self
+            .retry_backoff
+            .unwrap_or(DEFAULT_RETRY_BACKOFF)
+            .min(MAX_BACKOFF);
+        let mut attempt = 0;
+
+        loop {
+            let req = self.request(client, url)?.headers(headers.clone());
+            let delay = match self.timed(url, req.send()).await {
+                Ok(resp)
+                    if resp.status().is_success() || resp.status() == StatusCode::NOT_MODIFIED =>
+                {
+                    return self.read(url, resp).await
+                }
+                Ok(resp) if attempt < retries && retryable(resp.status()) => {
+                    retry_after(resp.headers()).unwrap_or(backoff)
+                }
+                Ok(resp) => return Err(self.status_error(url, resp).await),
+                Err(e) if attempt < retries && transient(&e) => backoff,
+                Err(e) => return Err(e),
+            };
+
+            attempt += 1;
+            tracing::debug!("retrying {} in {:?} ({}/{})", url, delay, attempt, retries);
+            tokio::time::sleep(delay).await;
+            backoff = next_backoff(backoff);
+        }
+    }
+
+    async fn read(&self, url: &str, mut resp: reqwest::Response) -> Result<Response> {
+        let too_large = |size: u64| match self.max_response_size {
+            Some(max) if size > max => Err(anyhow!(
+                "Response from {} exceeds the maximum size of {} bytes",
+                url,
+                max
+            )),
+            _ => Ok(()),
+        };
+
+        too_large(resp.content_length().unwrap_or(0))?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let mut body = Vec::new();
+        while let Some(chunk) = self.timed(url, resp.chunk()).await? {
+            body.extend_from_slice(&chunk);
+            too_large(body.len() as u64)?;
+        }
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// The error for a failed response, quoting the start of its body.
+    async fn status_error(&self, url: &str, mut resp: reqwest::Response) -> anyhow::Error {
+        let status = resp.status();
+        let mut body = Vec::new();
+        while body.len() < MAX_ERROR_BODY {
+            match self.timed(url, resp.chunk()).await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        body.truncate(MAX_ERROR_BODY);
+        let body = String::from_utf8_lossy(&body);
+        let excerpt: String = body.trim().chars().take(200).collect();
+        anyhow!("HTTP {} from {}: {}", status, url, excerpt)
+    }
+
+    /// Await `fut`, failing when it takes longer than the read timeout.
+    async fn timed<T>(
+        &self,
+        url: &str,
+        fut: impl Future<Output = reqwest::Result<T>>,
+    ) -> Result<T> {
+        match self.read_timeout {
+            Some(timeout) => Ok(tokio::time::timeout(timeout, fut)
+                .await
+                .with_context(|| format!("Timed out reading from {}", url))??),
+            None => Ok(fut.await?),
+        }
+    }
+
+    fn method(&self) -> Result<Method> {
+        match &self.method {
+            Some(m) => Ok(Method::from_bytes(m.to_uppercase().as_bytes())?),
+            None => Ok(Method::GET),
+        }
+    }
+
+    pub(crate) fn request(&self, client: &Client, url: &str) -> Result<RequestBuilder> {
+        let mut req = client.request(self.method()?, url);
+        if let Some(ua) = &self.user_agent {
+            req = req.header(reqwest::header::USER_AGENT, expand_env(ua)?);
+        }
+        for (name, value) in &self.headers {
+            req = req.header(name, expand_env(value)?);
+        }
+        req = match &self.auth {
+            Some(Auth::Bearer(token)) => req.bearer_auth(expand_env(token)?),
+            Some(Auth::Basic { username, password }) => {
+                let password = password.as_deref().map(expand_env).transpose()?;
+                req.basic_auth(expand_env(username)?, password)
+            }
+            None => req,
+        };
+        if let Some(body) = &self.body {
+            req = req.body(expand_env(body)?);
+        }
+
+        Ok(req)
+    }
+}
+
+impl FromStr for Pagination {
+    type Err = anyhow::Error;
+
+    /// Parse `link`, `cursor:<param>:<cursor path>`, `page:<param>[:<start>]`
+    /// or `offset:<param>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.splitn(3, ':').collect();
+        match parts.as_slice() {
+            ["link"] => Ok(Pagination::Link),
+            ["cursor", param, path] => Ok(Pagination::Cursor {
+                param: param.to_string(),
+                cursor_path: path.to_string(),
+            }),
+            ["page", param] => Ok(Pagination::Page {
+                param: param.to_string(),
+                start: first_page(),
+            }),
+            ["page", param, start] => Ok(Pagination::Page {
+                param: param.to_string(),
+                start: start.parse()?,
+            }),
+            ["offset", param] => Ok(Pagination::Offset {
+                param: param.to_string(),
+            }),
+            _ => Err(anyhow!(
+                "Invalid pagination {}, expected link, cursor:<param>:<path>, page:<param>[:<start>] or offset:<param>",
+                s
+            )),
+        }
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_timeout() || e.is_connect(),
+        None => e.is::<tokio::time::error::Elapsed>(),
+    }
+}
+
+/// The delay before the retry after one of `backoff`, doubled up to
+/// `MAX_BACKOFF`.
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF)
+}
+
+/// The delay asked for by a `Retry-After` header, in seconds or as a date,
+/// at most `MAX_RETRY_AFTER`.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            date.duration_since(SystemTime::now()).unwrap_or_default()
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+pub(crate) fn seconds<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+    Option::<f64>::deserialize(d)?
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Parse a `Name: value` header as given on the command line.
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(anyhow!("Invalid header {}, expected `Name: value`", header)),
+    }
+}
+
+/// Whether `name` is a header carrying credentials.
+fn is_credential(name: &str) -> bool {
+    CREDENTIAL_HEADERS
+        .iter()
+        .any(|h| name.eq_ignore_ascii_case(h.as_str()))
+}
+
+/// Replace every `${NAME}` in `value` with the environment variable `NAME`.
+pub fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated ${{ in {}", value))?;
+        let name = &rest[start + 2..start + end];
+        let var =
+            std::env::var(name).map_err(|_| anyhow!("Environment variable {} is not set", name))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&var);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_env_vars() {
+        std::env::set_var("XQ_TEST_TOKEN", "s3cr3t");
+        assert_eq!(
+            expand_env("Bearer ${XQ_TEST_TOKEN}").unwrap(),
+            "Bearer s3cr3t"
+        );
+        assert_eq!(expand_env("plain").unwrap(), "plain");
+        assert!(expand_env("${XQ_TEST_MISSING}").is_err());
+        assert!(expand_env("${XQ_TEST_TOKEN").is_err());
+    }
+
+    #[test]
+    fn retry_after_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(30)));
+        headers.insert(RETRY_AFTER, "86400".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(MAX_RETRY_AFTER));
+
+        assert_eq!(next_backoff(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(next_backoff(Duration::MAX), MAX_BACKOFF);
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parse_pagination() {
+        assert_eq!("link".parse::<Pagination>().unwrap(), Pagination::Link);
+        assert_eq!(
+            "cursor:after:meta.next".parse::<Pagination>().unwrap(),
+            Pagination::Cursor {
+                param: "after".into(),
+                cursor_path: "meta.next".into()
+            }
+        );
+        assert_eq!(
+            "page:p".parse::<Pagination>().unwrap(),
+            Pagination::Page {
+                param: "p".into(),
+                start: 1
+            }
+        );
+        assert_eq!(
+            "page:p:0".parse::<Pagination>().unwrap(),
+            Pagination::Page {
+                param: "p".into(),
+                start: 0
+            }
+        );
+        assert!("cursor:after".parse::<Pagination>().is_err());
+    }
+
+    #[test]
+    fn parse_headers() {
+        assert_eq!(
+            parse_header("X-Api-Key: abc:def").unwrap(),
+            ("X-Api-Key".to_string(), "abc:def".to_string())
+        );
+        assert!(parse_header("no separator").is_err());
+    }
+
+    #[test]
+    fn merge_options() {
+        let host = HttpOptions::default()
+            .with_header("X-Api-Key", "${KEY}")
+            .with_auth(Auth::Bearer("host".into()));
+        let cli = HttpOptions::default()
+            .with_method("POST")
+            .with_auth(Auth::Bearer("cli".into()));
+
+        let merged = host.merge(&cli);
+        assert_eq!(merged.method.as_deref(), Some("POST"));
+        assert_eq!(merged.headers["X-Api-Key"], "${KEY}");
+        assert!(matches!(merged.auth, Some(Auth::Bearer(t)) if t == "cli"));
+    }
+}
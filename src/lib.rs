@@ -1,18 +1,39 @@
+mod cache;
+mod compression;
 mod convert;
+mod database;
 mod dialect;
+mod encoding;
 mod fetcher;
+mod http;
 mod loader;
+mod logs;
+mod markup;
+mod options;
+mod parquet;
+mod partition;
+mod sqlite;
 use std::ops::{Deref, DerefMut};
 
 use anyhow::{anyhow, Result};
+pub use cache::{Cache, CacheEntry};
 pub use dialect::XQDialect;
+pub use http::{parse_header, Auth, HttpOptions, Pagination};
+pub use loader::{
+    parse_char, parse_columns, parse_fwf_columns, parse_sample_size, read_schema, CsvOptions,
+    ExcelOptions, Format, FwfOptions, JsonOptions,
+};
+pub use markup::{HtmlOptions, XmlOptions};
+pub use options::{default_config_path, QueryOptions};
 use polars::prelude::*;
 use prettytable::{Cell, Row, Table};
 use sqlparser::parser::Parser;
 
-use crate::convert::Sql;
-use crate::fetcher::retrieve_data;
-use crate::loader::detect_content;
+use crate::convert::{resolve_fields, DataSource, Sql, TableFunction};
+use crate::database::DatabaseSource;
+use crate::fetcher::{retrieve_data, Content, Data};
+use crate::loader::{detect_content, Loader};
+use crate::sqlite::SqliteSource;
 
 #[derive(Debug, Clone)]
 pub struct DataSet(pub DataFrame);
@@ -113,39 +134,61 @@ impl DataSet {
 }
 
 pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
+    query_with_options(sql, &QueryOptions::default()).await
+}
+
+pub async fn query_with_options<T: AsRef<str>>(sql: T, options: &QueryOptions) -> Result<DataSet> {
     let ast = Parser::parse_sql(&XQDialect, sql.as_ref())?;
     if ast.len() != 1 {
         return Err(anyhow!("Only support single sql at the moment"));
     };
 
-    let sql = &ast[0];
+    let sql: Sql = (&ast[0]).try_into()?;
+
+    tracing::debug!("retrieving data from source: {}", sql.source);
+
+    let mut lf = load_source(&sql, options).await?;
+    let schema = lf.schema()?;
 
     let Sql {
         selection,
-        source,
+        unnest,
         condition,
         group_by,
         aggregation,
         offset,
         limit,
         order_by,
-    } = sql.try_into()?;
-
-    tracing::debug!("retrieving data from source: {}", source);
-
-    let ds = detect_content(retrieve_data(source).await?).load()?;
+        ..
+    } = sql;
 
     // println!("group_by: {:?}", group_by.to_vec());
     // println!("selection: {:?}", selection);
 
+    let resolve = |exprs: Vec<Expr>| -> Vec<Expr> {
+        exprs
+            .into_iter()
+            .map(|e| resolve_fields(e, &schema))
+            .collect()
+    };
+    let (selection, group_by, aggregation) =
+        (resolve(selection), resolve(group_by), resolve(aggregation));
+    let condition = condition.map(|e| resolve_fields(e, &schema));
+
+    let lf = match unnest.is_empty() {
+        true => lf,
+        false => lf.explode(unnest.iter().map(|c| col(c)).collect::<Vec<_>>()),
+    };
+
     let mut filtered = match condition {
-        Some(expr) => ds.0.lazy().filter(expr),
-        None => ds.0.lazy(),
+        Some(expr) => lf.filter(expr),
+        None => lf,
     };
 
     // println!("aggregation: {:?}", aggregation.to_vec());
 
-    if !aggregation.is_empty() {
+    let aggregated = !aggregation.is_empty();
+    if aggregated {
         if !group_by.is_empty() {
             filtered = filtered.group_by(group_by).agg(aggregation);
         } else {
@@ -155,9 +198,17 @@ pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
     }
 
     if !order_by.is_empty() {
-        let cols: Vec<_> = order_by.iter().map(|(col, _)| col.clone()).collect();
+        // sorting precedes the selection, so struct fields are resolved
+        // unless the rows were aggregated
+        let cols: Vec<_> = order_by
+            .iter()
+            .map(|(name, _)| match aggregated {
+                true => col(name),
+                false => resolve_fields(col(name), &schema),
+            })
+            .collect();
         let descending: Vec<_> = order_by.iter().map(|(_, desc)| *desc).collect();
-        filtered = filtered.sort(
+        filtered = filtered.sort_by_exprs(
             cols,
             SortMultipleOptions {
                 descending,
@@ -175,6 +226,171 @@ pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
     Ok(DataSet(filtered.select(selection).collect()?))
 }
 
+/// Read the source of `sql` into a frame.
+async fn load_source(sql: &Sql<'_>, options: &QueryOptions) -> Result<LazyFrame> {
+    match &sql.source {
+        DataSource::Uri(uri) if uri.starts_with("sqlite://") => {
+            Ok(SqliteSource::parse(uri)?.load().await?.lazy())
+        }
+        DataSource::Uri(uri) if DatabaseSource::is_database(uri) => {
+            Ok(DatabaseSource::parse(uri)?.load(sql).await?.lazy())
+        }
+        DataSource::Uri(uri) => read_uri(uri, sql, options).await,
+        DataSource::Values(df) => Ok(df.clone().lazy()),
+        DataSource::Function(func) => match func.name.as_str() {
+            "sqlite" => Ok(SqliteSource::from_function(func)?.load().await?.lazy()),
+            "read_csv" | "read_json" | "read_ndjson" | "read_parquet" | "read_excel"
+            | "read_regex" | "read_xml" | "read_html" | "read_yaml" | "read_toml" | "read_fwf" => {
+                let (func, options) = take_encoding(func, options)?;
+                let (func, options) = (&func, &options);
+                let source = func
+                    .string(0, "source")?
+                    .ok_or_else(|| anyhow!("{} needs a source", func.name))?;
+                let options = match func.name.as_str() {
+                    "read_csv" => options
+                        .clone()
+                        .with_format(Some(Format::Csv))
+                        .with_csv(CsvOptions::from_function(func)?),
+                    "read_json" => options
+                        .clone()
+                        .with_format(Some(Format::Json))
+                        .with_json(JsonOptions::from_function(func)?),
+                    "read_ndjson" => options
+                        .clone()
+                        .with_format(Some(Format::Ndjson))
+                        .with_json(JsonOptions::from_function(func)?),
+                    "read_excel" => options
+                        .clone()
+                        .with_format(Some(Format::Excel))
+                        .with_excel(ExcelOptions::from_function(func)?),
+                    "read_fwf" => options
+                        .clone()
+                        .with_format(Some(Format::Fwf))
+                        .with_fwf(FwfOptions::from_function(func)?),
+                    "read_yaml" => options
+                        .clone()
+                        .with_format(Some(Format::Yaml))
+                        .with_json(JsonOptions::from_function(func)?),
+                    "read_toml" => options
+                        .clone()
+                        .with_format(Some(Format::Toml))
+                        .with_json(JsonOptions::from_function(func)?),
+                    "read_xml" => options
+                        .clone()
+                        .with_format(Some(Format::Xml))
+                        .with_xml(XmlOptions::from_function(func)?),
+                    "read_html" => options
+                        .clone()
+                        .with_format(Some(Format::Html))
+                        .with_html(HtmlOptions::from_function(func)?),
+                    "read_regex" => {
+                        func.check(2, &["source", "pattern"])?;
+                        let pattern = func
+                            .string(1, "pattern")?
+                            .ok_or_else(|| anyhow!("read_regex needs a pattern"))?;
+                        options
+                            .clone()
+                            .with_format(Some(Format::Regex))
+                            .with_pattern(Some(pattern))
+                    }
+                    _ => {
+                        func.check(1, &["source"])?;
+                        options.clone().with_format(Some(Format::Parquet))
+                    }
+                };
+                read_uri(&source, sql, &options).await
+            }
+            name => Err(anyhow!("Unknown table function {}", name)),
+        },
+    }
+}
+
+/// Move the `encoding` of a text reader, e.g. `read_csv(source, encoding =>
+/// 'gbk')`, into the options.
+fn take_encoding(
+    func: &TableFunction,
+    options: &QueryOptions,
+) -> Result<(TableFunction, QueryOptions)> {
+    let mut func = func.clone();
+    let mut options = options.clone();
+    if matches!(func.name.as_str(), "read_parquet" | "read_excel") {
+        return Ok((func, options));
+    }
+    if let Some(encoding) = func.string(usize::MAX, "encoding")? {
+        options.encoding = Some(encoding);
+        func.named_args
+            .retain(|(name, _)| !name.eq_ignore_ascii_case("encoding"));
+    }
+    Ok((func, options))
+}
+
+async fn read_uri(uri: &str, sql: &Sql<'_>, options: &QueryOptions) -> Result<LazyFrame> {
+    // cached responses hold whole files, range requests bypass the cache
+    if options.cache.is_none() && parquet::is_remote(uri, options.format) {
+        let http = options.http_for(uri);
+        let content = Content {
+            location: uri.to_string(),
+            partitions: partition::from_url(uri),
+            format: Some(Format::Parquet),
+            fragment: None,
+            data: Data::Frame(parquet::read_remote(uri, sql, &http).await?),
+        };
+        return load(vec![content], options);
+    }
+    let contents = retrieve_data(uri, sql.condition.as_ref(), options).await?;
+    load(contents, options)
+}
+
+/// Load every fetched content and stack them into a single frame, unifying
+/// their schemas.
+fn load(contents: Vec<Content>, options: &QueryOptions) -> Result<LazyFrame> {
+    let mut frames = Vec::with_capacity(contents.len());
+    for Content {
+        location,
+        partitions,
+        format,
+        fragment,
+        data,
+    } in contents
+    {
+        let mut lf = match data {
+            Data::Parquet(path) => LazyFrame::scan_parquet(path, ScanArgsParquet::default())?,
+            Data::Frame(df) => df.lazy(),
+            Data::Bytes(data) => {
+                let mut loader = match options.format {
+                    Some(format) => format.loader(data, options),
+                    None => detect_content(&location, format, data, options),
+                };
+                match (&mut loader, fragment) {
+                    (Loader::Excel(excel), Some(sheet)) => excel.1.sheet = Some(sheet),
+                    // a fragment naming no table points elsewhere in the page
+                    (Loader::Html(html), Some(table)) if markup::has_table(&html.0, &table) => {
+                        html.1.table = Some(table)
+                    }
+                    _ => {}
+                }
+                loader.load()?.0.lazy()
+            }
+        };
+        lf = partition::attach(lf, &partitions)?;
+        if options.filename_column {
+            lf = lf.with_column(lit(location.as_str()).alias("_filename"));
+        }
+        frames.push(lf);
+    }
+
+    match frames.len() {
+        1 => Ok(frames.remove(0)),
+        _ => Ok(concat_lf_diagonal(
+            frames,
+            UnionArgs {
+                to_supertypes: true,
+                ..Default::default()
+            },
+        )?),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,9 +456,550 @@ mod tests {
         std::fs::remove_file(temp_file).ok();
     }
 
+    #[tokio::test]
+    async fn test_glob_and_partitions() {
+        let dir = std::env::temp_dir().join("xq_test_partitions");
+        std::fs::remove_dir_all(&dir).ok();
+        for (year, data) in [
+            ("2023", "name,value\nAlice,1\nBob,2"),
+            ("2024", "name,value,score\nCharlie,3.5,80\nDave,4,90"),
+        ] {
+            let part = dir.join(format!("year={}", year));
+            std::fs::create_dir_all(&part).unwrap();
+            std::fs::write(part.join("data.csv"), data).unwrap();
+        }
+
+        let sql = format!("SELECT * FROM file://{}/", dir.display());
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 4);
+        assert_eq!(df.get_column_names(), ["name", "value", "year", "score"]);
+
+        let sql = format!(
+            "SELECT name, _filename FROM 'file://{}/*/*.csv' WHERE year = 2024",
+            dir.display()
+        );
+        let options = QueryOptions::default().with_filename_column(true);
+        let df = query_with_options(&sql, &options).await.unwrap();
+        assert_eq!(df.height(), 2);
+        assert!(df
+            .column("_filename")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .all(|f| f.contains("year=2024")));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_compressed_query() {
+        use std::io::Write;
+
+        let csv_data = "name,age,score\nAlice,20,85\nBob,22,90\nCharlie,21,88";
+        let temp_file = std::env::temp_dir().join("test_compressed.csv.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(csv_data.as_bytes()).unwrap();
+        std::fs::write(&temp_file, gz.finish().unwrap()).unwrap();
+
+        let sql = format!(
+            "SELECT name FROM file://{} WHERE age > 20",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 2);
+
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_explicit_format() {
+        // the explicit format wins over content detection
+        let temp_file = std::env::temp_dir().join("test_format.txt");
+        std::fs::write(&temp_file, "{\"name\": \"Alice\"}").unwrap();
+
+        let sql = format!("SELECT * FROM file://{}", temp_file.display());
+        let options = QueryOptions::default().with_format(Some("csv".parse().unwrap()));
+        let df = query_with_options(&sql, &options).await.unwrap();
+        assert_eq!(df.get_column_names(), ["{\"name\": \"Alice\"}"]);
+
+        let options = QueryOptions::default().with_format(Some(Format::Json));
+        let df = query_with_options(&sql, &options).await.unwrap();
+        assert_eq!(df.get_column_names(), ["name"]);
+
+        assert_eq!("xml".parse::<Format>().unwrap(), Format::Xml);
+        assert!("pdf".parse::<Format>().is_err());
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_inline_data() {
+        let sql =
+            "SELECT name FROM (VALUES (1, 'a'), (2, 'b'), (-3, NULL)) AS t(id, name) WHERE id > 1";
+        let df = query(sql).await.unwrap();
+        assert_eq!(df.height(), 1);
+        assert_eq!(df.column("name").unwrap().str().unwrap().get(0), Some("b"));
+
+        let sql =
+            "SELECT * FROM 'data:text/csv;base64,bmFtZSxhZ2UKQWxpY2UsMjAKQm9iLDIy' WHERE age > 20";
+        let df = query(sql).await.unwrap();
+        assert_eq!(
+            df.column("name").unwrap().str().unwrap().get(0),
+            Some("Bob")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_html_fragments() {
+        let temp_file = std::env::temp_dir().join("test_fragments.html");
+        std::fs::write(
+            &temp_file,
+            "<h2 id=\"section\">Menu</h2><table><tr><th>a</th></tr><tr><td>1</td></tr></table>\
+             <table id=\"sales\"><tr><th>b</th></tr><tr><td>2</td></tr></table>",
+        )
+        .unwrap();
+
+        let df = query(format!(
+            "SELECT * FROM file://{}#sales",
+            temp_file.display()
+        ))
+        .await
+        .unwrap();
+        assert_eq!(df.get_column_names(), ["b"]);
+        // an anchor elsewhere in the page reads the first table
+        let df = query(format!(
+            "SELECT * FROM file://{}#section",
+            temp_file.display()
+        ))
+        .await
+        .unwrap();
+        assert_eq!(df.get_column_names(), ["a"]);
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_parquet_query() {
+        let dir = std::env::temp_dir().join("xq_test_parquet");
+        std::fs::remove_dir_all(&dir).ok();
+        for year in [2023, 2024] {
+            let mut df = df! {
+                "name" => &["Alice", "Bob"],
+                "age" => &[20i64, year - 2000],
+            }
+            .unwrap();
+            let part = dir.join(format!("year={}", year));
+            std::fs::create_dir_all(&part).unwrap();
+            let file = std::fs::File::create(part.join("data.parquet")).unwrap();
+            ParquetWriter::new(file).finish(&mut df).unwrap();
+        }
+
+        let sql = format!(
+            "SELECT name, age FROM file://{}/ WHERE year = 2024 AND age > 20",
+            dir.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 1);
+        assert_eq!(df.column("age").unwrap().i64().unwrap().get(0), Some(24));
+
+        // detected by the magic bytes without the extension
+        let file = dir.join("year=2023/data.parquet");
+        let renamed = dir.join("data.bin");
+        std::fs::copy(file, &renamed).unwrap();
+        let sql = format!("SELECT * FROM read_parquet('file://{}')", renamed.display());
+        assert_eq!(query(&sql).await.unwrap().height(), 2);
+        let sql = format!("SELECT * FROM file://{}", renamed.display());
+        assert_eq!(query(&sql).await.unwrap().height(), 2);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_remote_parquet() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mut df = df! { "name" => &["Alice", "Bob"] }.unwrap();
+        let mut file = Vec::new();
+        ParquetWriter::new(&mut file).finish(&mut df).unwrap();
+        let server = MockServer::start().await;
+        // the second query with the cache is answered from it
+        Mock::given(path("/year=2024/data.parquet"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(file))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/year=2024/data.parquet", server.uri());
+        let sql = format!(
+            "SELECT name, year, _filename FROM {} WHERE year = 2024",
+            url
+        );
+        let dir = std::env::temp_dir().join("xq_test_remote_parquet");
+        let cache = Cache::new(&dir);
+        cache.clear().unwrap();
+        let options = QueryOptions::default().with_filename_column(true);
+        for options in [
+            options.clone(),
+            options.clone().with_cache(Some(cache.clone())),
+            options.with_cache(Some(cache.clone())),
+        ] {
+            let df = query_with_options(&sql, &options).await.unwrap();
+            assert_eq!(df.height(), 2);
+            assert_eq!(df.column("year").unwrap().i64().unwrap().get(0), Some(2024));
+            assert_eq!(
+                df.column("_filename").unwrap().str().unwrap().get(0),
+                Some(url.as_str())
+            );
+        }
+        cache.clear().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_functions() {
+        let temp_file = std::env::temp_dir().join("test_read_csv.txt");
+        std::fs::write(&temp_file, "exported by x\nAlice|20|NA\nBob|22|90").unwrap();
+
+        let sql = format!(
+            "SELECT * FROM read_csv('file://{}', delim => '|', header => false, skip => 1, \
+             nullstr => 'NA', columns => 'name VARCHAR, age BIGINT, score DOUBLE')",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.get_column_names(), ["name", "age", "score"]);
+        assert_eq!(
+            df.dtypes(),
+            [DataType::String, DataType::Int64, DataType::Float64]
+        );
+        assert_eq!(df.column("score").unwrap().null_count(), 1);
+
+        // quoted names and types, as in a JSON object
+        let sql = format!(
+            "SELECT * FROM read_csv('file://{}', delim => '|', header => false, skip => 1, \
+             nullstr => 'NA', columns => '{{''name'': ''VARCHAR'', \"age\": \"BIGINT\", score: ''DOUBLE''}}')",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.get_column_names(), ["name", "age", "score"]);
+        assert_eq!(
+            df.dtypes(),
+            [DataType::String, DataType::Int64, DataType::Float64]
+        );
+
+        std::fs::write(&temp_file, "# v1\nname;score\nAlice;1,5\nBob;-\nEve;NA\n").unwrap();
+        let sql = format!(
+            "SELECT SUM(score) AS total FROM read_csv('file://{}', comment => '#', \
+             decimal_comma => true, nullstrs => 'NA,-')",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.column("total").unwrap().f64().unwrap().get(0), Some(1.5));
+
+        // a single null value may hold a comma
+        std::fs::write(&temp_file, "name,score\n\"x,y\",1\nz,2\n").unwrap();
+        let sql = format!(
+            "SELECT * FROM read_csv('file://{}', nullstr => 'x,y')",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.column("name").unwrap().null_count(), 1);
+
+        let schema_file = std::env::temp_dir().join("test_read_csv_schema.json");
+        std::fs::write(&schema_file, r#"{"name": "VARCHAR", "score": "DOUBLE"}"#).unwrap();
+        std::fs::write(
+            &temp_file,
+            "name,score,day\nAlice,1,2024-01-31\nBob,x,2024-02-01\n",
+        )
+        .unwrap();
+        let sql = format!(
+            "SELECT * FROM read_csv('file://{}', schema => '{}', columns => {{day: 'DATE'}}, \
+             sample_size => 'all', lenient => true)",
+            temp_file.display(),
+            schema_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(
+            df.dtypes(),
+            [DataType::String, DataType::Float64, DataType::Date]
+        );
+        assert_eq!(df.column("score").unwrap().null_count(), 1);
+        std::fs::remove_file(schema_file).ok();
+        std::fs::remove_file(temp_file).ok();
+
+        let temp_file = std::env::temp_dir().join("test_read_json.json");
+        std::fs::write(&temp_file, r#"{"data": {"items": [{"a": 1}, {"a": 2}]}}"#).unwrap();
+        let sql = format!(
+            "SELECT a FROM read_json('file://{}', records_path => 'data.items') WHERE a > 1",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 1);
+
+        let sql = format!(
+            "SELECT * FROM read_json('file://{}', delim => ',')",
+            temp_file.display()
+        );
+        assert!(query(&sql).await.is_err());
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_query() {
+        let temp_file = std::env::temp_dir().join("test_app.log");
+        std::fs::write(
+            &temp_file,
+            "{\"level\": \"info\", \"ms\": 12}\n{\"level\": \"error\", \"ms\": 40}\nnot json\n",
+        )
+        .unwrap();
+
+        let sql = format!(
+            "SELECT level FROM read_ndjson('file://{}', ignore_errors => true) WHERE ms > 20",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 1);
+
+        let sql = format!("SELECT * FROM file://{}", temp_file.display());
+        let err = query(&sql).await.unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_nested_json() {
+        let temp_file = std::env::temp_dir().join("test_nested.json");
+        std::fs::write(
+            &temp_file,
+            r#"{"data": {"items": [
+                {"id": 1, "tags": ["a", "b"], "user": {"name": "x"}},
+                {"id": 2, "tags": ["c"], "user": {"name": "y"}}
+            ]}, "meta": {"total": 2}}"#,
+        )
+        .unwrap();
+
+        let sql = format!(
+            "SELECT id, UNNEST(tags) AS tag FROM read_json('file://{}', \
+             records_path => '$.data.items') WHERE tags <> 'c'",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.get_column_names(), ["id", "tag"]);
+        assert_eq!(df.height(), 2);
+
+        let sql = format!(
+            "SELECT id FROM read_json('file://{}', records_path => 'data.items', \
+             flatten => true) WHERE user.name = 'y'",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.column("id").unwrap().i64().unwrap().get(0), Some(2));
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_config_files() {
+        let dir = std::env::temp_dir().join("test_k8s");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("app.yaml"),
+            "kind: Deployment\nmetadata:\n  name: web\n---\nkind: Service\nmetadata:\n  name: web-svc\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("worker.yml"),
+            "---\nkind: Deployment\nmetadata:\n  name: worker\n  labels: {tier: jobs}\n",
+        )
+        .unwrap();
+
+        let sql = format!(
+            "SELECT metadata.name FROM 'file://{}/*.y*ml' WHERE kind = 'Deployment' \
+             ORDER BY metadata.name",
+            dir.display()
+        );
+        let df = query(&sql).await.unwrap();
+        let names: Vec<_> = df
+            .column("metadata.name")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(names, [Some("web"), Some("worker")]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_field_access() {
+        let temp_file = std::env::temp_dir().join("test_fields.json");
+        std::fs::write(
+            &temp_file,
+            r#"[
+                {"id": 1, "tags": ["a", "b"], "user": {"name": "x", "address": {"city": "p"}},
+                 "payload": "{\"user\": {\"name\": \"x\"}, \"n\": [5]}"},
+                {"id": 2, "tags": ["c"], "user": {"name": "y", "address": {"city": "q"}},
+                 "payload": "{\"user\": {\"name\": \"y\"}, \"n\": [6]}"}
+            ]"#,
+        )
+        .unwrap();
+
+        let sql = format!(
+            "SELECT id, user.address.city, tags[0] AS tag, user['name'] AS name, \
+             payload -> 'user' ->> 'name' AS who, json_extract(payload, '$.n[0]') AS n, \
+             payload -> 'user' -> 'name' AS quoted, payload -> 'user' AS doc \
+             FROM file://{} WHERE user.address.city = 'q'",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(
+            df.get_column_names(),
+            [
+                "id",
+                "user.address.city",
+                "tag",
+                "name",
+                "who",
+                "n",
+                "quoted",
+                "doc"
+            ]
+        );
+        let row: Vec<_> = df.get_row(0).unwrap().0;
+        assert_eq!(
+            row,
+            [
+                AnyValue::Int64(2),
+                AnyValue::String("q"),
+                AnyValue::String("c"),
+                AnyValue::String("y"),
+                AnyValue::String("y"),
+                AnyValue::String("6"),
+                AnyValue::String("\"y\""),
+                AnyValue::String(r#"{"name":"y"}"#),
+            ]
+        );
+
+        // ordered by a struct field
+        let sql = format!(
+            "SELECT id FROM file://{} ORDER BY user.address.city DESC",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        let ids: Vec<_> = df
+            .column("id")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(ids, [Some(2), Some(1)]);
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_log_query() {
+        let temp_file = std::env::temp_dir().join("test_access.log");
+        std::fs::write(
+            &temp_file,
+            "1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] \"GET / HTTP/1.1\" 200 10\n\
+             1.2.3.4 - - [10/Oct/2000:13:55:37 -0700] \"GET /x HTTP/1.1\" 404 -\n",
+        )
+        .unwrap();
+
+        let sql = format!(
+            "SELECT path FROM file://{} WHERE status >= 400",
+            temp_file.display()
+        );
+        let options = QueryOptions::default().with_format(Some(Format::AccessLog));
+        let df = query_with_options(&sql, &options).await.unwrap();
+        assert_eq!(df.column("path").unwrap().str().unwrap().get(0), Some("/x"));
+
+        let sql = format!(
+            "SELECT code, COUNT(*) FROM read_regex('file://{}', '\" (?P<code>\\d+) ') GROUP BY code",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 2);
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_fixed_width_query() {
+        let temp_file = std::env::temp_dir().join("test_fixed.txt");
+        std::fs::write(&temp_file, "00001ALICE     00120\n00002BOB       00075\n").unwrap();
+
+        let sql = format!(
+            "SELECT name FROM read_fwf('file://{}', columns => 'id:1:5, name:6:10, amount:16:5') \
+             WHERE amount > 100",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(
+            df.column("name").unwrap().str().unwrap().get(0),
+            Some("ALICE")
+        );
+        assert_eq!(df.height(), 1);
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_encodings() {
+        let temp_file = std::env::temp_dir().join("test_big5.csv");
+        let (big5, _, _) = encoding_rs::BIG5.encode("city,pop\n臺北,260\n高雄,270\n");
+        std::fs::write(&temp_file, big5).unwrap();
+
+        let sql = format!(
+            "SELECT city FROM read_csv('file://{}', encoding => 'big5') WHERE pop > 265",
+            temp_file.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(
+            df.column("city").unwrap().str().unwrap().get(0),
+            Some("高雄")
+        );
+
+        // UTF-16 with a byte order mark, as exported on Windows
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("a\tb\r\n1\t2\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        std::fs::write(&temp_file, utf16).unwrap();
+        let df = query(format!("SELECT b FROM file://{}", temp_file.display()))
+            .await
+            .unwrap();
+        assert_eq!(df.column("b").unwrap().i64().unwrap().get(0), Some(2));
+        std::fs::remove_file(temp_file).ok();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query() {
+        let path = std::env::temp_dir().join("test_query.db");
+        std::fs::remove_file(&path).ok();
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE events (name TEXT, age INTEGER);
+                 INSERT INTO events VALUES ('Alice', 20), ('Bob', 22), ('Charlie', 21);
+                 CREATE TABLE other (x TEXT);",
+            )
+            .unwrap();
+
+        let sql = format!(
+            "SELECT name FROM sqlite://{}?table=events WHERE age > 20",
+            path.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.height(), 2);
+
+        let sql = format!(
+            "SELECT * FROM sqlite('{}', query => 'SELECT age FROM events WHERE age < 22')",
+            path.display()
+        );
+        let df = query(&sql).await.unwrap();
+        assert_eq!(df.get_column_names(), ["age"]);
+        assert_eq!(df.height(), 2);
+
+        std::fs::remove_file(path).ok();
+    }
+
     #[test]
     fn test_dataset_to_csv() {
-        use polars::df;
         let df = df! {
             "name" => &["Alice", "Bob"],
             "age" => &[20, 22],
@@ -1,9 +1,14 @@
 use anyhow::{anyhow, Result};
+use calamine::Reader;
 use polars::prelude::*;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::num::NonZero;
+use std::str::FromStr;
 
-use crate::DataSet;
+use crate::convert::TableFunction;
+use crate::logs::{LogFormat, LogLoader};
+use crate::markup::{HtmlLoader, XmlLoader};
+use crate::{DataSet, QueryOptions};
 
 pub trait Load {
     type Error;
@@ -15,59 +20,2103 @@ pub trait Load {
 pub enum Loader {
     Csv(CsvLoader),
     Json(JsonLoader),
+    Ndjson(NdjsonLoader),
+    Parquet(ParquetLoader),
+    Excel(ExcelLoader),
+    Arrow(ArrowLoader),
+    Avro(AvroLoader),
+    Log(LogLoader),
+    Xml(XmlLoader),
+    Html(HtmlLoader),
+    Yaml(YamlLoader),
+    Toml(TomlLoader),
+    Fwf(FwfLoader),
 }
 
+/// Format of the data, used to skip content detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    /// Newline-delimited JSON, one record per line.
+    Ndjson,
+    Parquet,
+    /// Spreadsheets: xlsx, xlsm, xlsb, xls and ods.
+    Excel,
+    /// Arrow IPC, the file (Feather v2) or the stream format.
+    Arrow,
+    Avro,
+    /// Apache/Nginx common or combined access log.
+    AccessLog,
+    /// RFC 5424 or RFC 3164 syslog.
+    Syslog,
+    /// `key=value` pairs, a line per record.
+    Logfmt,
+    /// Lines matched by the `pattern` of the query options.
+    Regex,
+    /// Repeated XML elements, a row each.
+    Xml,
+    /// A `<table>` of an HTML page.
+    Html,
+    /// YAML documents, a row per document or per item of a top-level list.
+    Yaml,
+    Toml,
+    /// Fixed-width columns, a row per line.
+    Fwf,
+}
+
+/// How CSV data is read, detected from the data when unset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CsvOptions {
+    pub delimiter: Option<u8>,
+    pub has_header: Option<bool>,
+    pub quote: Option<u8>,
+    /// Character escaping a quote or itself inside quoted fields, e.g. `\`,
+    /// quotes are escaped by doubling them when unset.
+    pub escape: Option<u8>,
+    /// Lines starting with this prefix, e.g. `#`, are skipped.
+    pub comment_prefix: Option<String>,
+    /// Lines skipped before the header.
+    pub skip_rows: usize,
+    /// Values read as null.
+    pub null_values: Vec<String>,
+    /// Whether empty fields are null rather than empty strings, true when
+    /// unset.
+    pub missing_is_null: Option<bool>,
+    /// Read floats with a decimal comma, e.g. `1,5`.
+    pub decimal_comma: bool,
+    /// Drop the fields of lines longer than the header instead of failing.
+    pub truncate_ragged_lines: bool,
+    /// Names and types of the columns. With a header, the types of the
+    /// named columns, without one, every column.
+    pub columns: Option<Vec<(String, DataType)>>,
+    /// Rows the types of the columns are inferred from, 16 when unset and
+    /// `usize::MAX` for every row.
+    pub infer_schema_length: Option<usize>,
+    /// Read values that do not parse as the type of their column as null,
+    /// warning how many, instead of failing.
+    pub lenient: bool,
+}
+
+/// How JSON data is read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonOptions {
+    /// Path of the records array, e.g. `data.items` or `$.data['items']`.
+    pub records_path: Option<String>,
+    /// Flatten struct columns into `a.b.c` columns.
+    pub flatten: bool,
+    /// Skip malformed lines of newline-delimited JSON instead of failing.
+    pub ignore_errors: bool,
+    /// Types of the named columns, e.g. `ts TIMESTAMP`.
+    pub columns: Option<Vec<(String, DataType)>>,
+    /// Rows the types of the columns are inferred from, 100 when unset and
+    /// `usize::MAX` for every row.
+    pub infer_schema_length: Option<usize>,
+    /// Read values of the named columns that do not convert to their type
+    /// as null, warning how many, instead of failing.
+    pub lenient: bool,
+}
+
+/// How spreadsheets are read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExcelOptions {
+    /// Sheet read, the first one when unset.
+    pub sheet: Option<String>,
+    /// Cells read, e.g. `B3:F100`, or `B3` for the cells from B3 on.
+    pub range: Option<String>,
+    /// Whether the first row of the range names the columns, true when unset.
+    pub has_header: Option<bool>,
+}
+
+/// How fixed-width data is read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FwfOptions {
+    /// Name, start and width of every column, the start counted from 1.
+    /// Inferred from the header line when unset.
+    pub columns: Option<Vec<(String, usize, usize)>>,
+    /// Whether a header line names the columns, true when unset unless
+    /// `columns` is set.
+    pub has_header: Option<bool>,
+    /// Lines skipped before the header.
+    pub skip_rows: usize,
+}
+
+#[derive(Default, Debug)]
+pub struct CsvLoader(pub(crate) Vec<u8>, pub(crate) CsvOptions);
+
+#[derive(Default, Debug)]
+pub struct JsonLoader(pub(crate) Vec<u8>, pub(crate) JsonOptions);
+
+#[derive(Default, Debug)]
+pub struct NdjsonLoader(pub(crate) Vec<u8>, pub(crate) JsonOptions);
+
+#[derive(Default, Debug)]
+pub struct FwfLoader(pub(crate) Vec<u8>, pub(crate) FwfOptions);
+
+#[derive(Default, Debug)]
+pub struct YamlLoader(pub(crate) Vec<u8>, pub(crate) JsonOptions);
+
+#[derive(Default, Debug)]
+pub struct TomlLoader(pub(crate) Vec<u8>, pub(crate) JsonOptions);
+
+#[derive(Default, Debug)]
+pub struct ParquetLoader(pub(crate) Vec<u8>);
+
+#[derive(Default, Debug)]
+pub struct ExcelLoader(pub(crate) Vec<u8>, pub(crate) ExcelOptions);
+
 #[derive(Default, Debug)]
-pub struct CsvLoader(pub(crate) String);
+pub struct ArrowLoader(pub(crate) Vec<u8>);
 
 #[derive(Default, Debug)]
-pub struct JsonLoader(pub(crate) String);
+pub struct AvroLoader(pub(crate) Vec<u8>);
 
 impl Loader {
     pub fn load(self) -> Result<DataSet> {
         match self {
             Loader::Csv(csv) => csv.load(),
             Loader::Json(json) => json.load(),
+            Loader::Ndjson(ndjson) => ndjson.load(),
+            Loader::Parquet(parquet) => parquet.load(),
+            Loader::Excel(excel) => excel.load(),
+            Loader::Arrow(arrow) => arrow.load(),
+            Loader::Avro(avro) => avro.load(),
+            Loader::Log(log) => log.load(),
+            Loader::Xml(xml) => xml.load(),
+            Loader::Html(html) => html.load(),
+            Loader::Yaml(yaml) => yaml.load(),
+            Loader::Toml(toml) => toml.load(),
+            Loader::Fwf(fwf) => fwf.load(),
+        }
+    }
+}
+
+impl Format {
+    pub(crate) fn loader(self, data: Vec<u8>, options: &QueryOptions) -> Loader {
+        match self {
+            Format::Csv => Loader::Csv(CsvLoader(data, options.csv.clone())),
+            Format::Json => Loader::Json(JsonLoader(data, options.json.clone())),
+            Format::Ndjson => Loader::Ndjson(NdjsonLoader(data, options.json.clone())),
+            Format::Parquet => Loader::Parquet(ParquetLoader(data)),
+            Format::Excel => Loader::Excel(ExcelLoader(data, options.excel.clone())),
+            Format::Arrow => Loader::Arrow(ArrowLoader(data)),
+            Format::Avro => Loader::Avro(AvroLoader(data)),
+            Format::AccessLog => Loader::Log(LogLoader(data, LogFormat::Access)),
+            Format::Syslog => Loader::Log(LogLoader(data, LogFormat::Syslog)),
+            Format::Logfmt => Loader::Log(LogLoader(data, LogFormat::Logfmt)),
+            Format::Regex => {
+                let pattern = options.pattern.clone().unwrap_or_default();
+                Loader::Log(LogLoader(data, LogFormat::Regex(pattern)))
+            }
+            Format::Xml => Loader::Xml(XmlLoader(data, options.xml.clone())),
+            Format::Html => Loader::Html(HtmlLoader(data, options.html.clone())),
+            Format::Yaml => Loader::Yaml(YamlLoader(data, options.json.clone())),
+            Format::Toml => Loader::Toml(TomlLoader(data, options.json.clone())),
+            Format::Fwf => Loader::Fwf(FwfLoader(data, options.fwf.clone())),
+        }
+    }
+
+    /// The format named by a media type such as `text/csv; charset=utf-8`,
+    /// if any.
+    pub(crate) fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.split(';').next()?.trim().to_lowercase().as_str() {
+            "text/csv" | "text/tab-separated-values" => Some(Format::Csv),
+            "application/json" | "text/json" => Some(Format::Json),
+            "application/x-ndjson" | "application/jsonl" | "application/jsonlines" => {
+                Some(Format::Ndjson)
+            }
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            | "application/vnd.ms-excel"
+            | "application/vnd.oasis.opendocument.spreadsheet" => Some(Format::Excel),
+            "application/xml" | "text/xml" => Some(Format::Xml),
+            "text/html" => Some(Format::Html),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(Format::Yaml),
+            "application/toml" => Some(Format::Toml),
+            "application/vnd.apache.arrow.file" | "application/vnd.apache.arrow.stream" => {
+                Some(Format::Arrow)
+            }
+            "application/avro" | "avro/binary" => Some(Format::Avro),
+            _ => None,
+        }
+    }
+
+    /// The format implied by the extension of `location`, if any, looking
+    /// through a compression extension, e.g. `.csv.gz`.
+    pub(crate) fn from_extension(location: &str) -> Option<Self> {
+        let path = location.split(['?', '#']).next().unwrap_or(location);
+        let mut path = std::path::Path::new(path);
+        let mut ext = path.extension()?.to_str()?.to_lowercase();
+        if matches!(ext.as_str(), "gz" | "gzip" | "zst" | "zstd" | "bz2" | "xz") {
+            path = std::path::Path::new(path.file_stem()?);
+            ext = path.extension()?.to_str()?.to_lowercase();
+        }
+        match ext.as_str() {
+            "csv" | "tsv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "parquet" | "pq" => Some(Format::Parquet),
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => Some(Format::Excel),
+            "arrow" | "arrows" | "feather" | "ipc" => Some(Format::Arrow),
+            "avro" => Some(Format::Avro),
+            "xml" => Some(Format::Xml),
+            "html" | "htm" => Some(Format::Html),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn with_header(mut self, has_header: bool) -> Self {
+        self.has_header = Some(has_header);
+        self
+    }
+
+    pub fn with_quote(mut self, quote: u8) -> Self {
+        self.quote = Some(quote);
+        self
+    }
+
+    pub fn with_escape(mut self, escape: u8) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    pub fn with_comment_prefix(mut self, comment_prefix: impl Into<String>) -> Self {
+        self.comment_prefix = Some(comment_prefix.into());
+        self
+    }
+
+    pub fn with_skip_rows(mut self, skip_rows: usize) -> Self {
+        self.skip_rows = skip_rows;
+        self
+    }
+
+    pub fn with_null_values(mut self, null_values: Vec<String>) -> Self {
+        self.null_values = null_values;
+        self
+    }
+
+    pub fn with_missing_is_null(mut self, missing_is_null: bool) -> Self {
+        self.missing_is_null = Some(missing_is_null);
+        self
+    }
+
+    pub fn with_decimal_comma(mut self, decimal_comma: bool) -> Self {
+        self.decimal_comma = decimal_comma;
+        self
+    }
+
+    pub fn with_truncate_ragged_lines(mut self, truncate_ragged_lines: bool) -> Self {
+        self.truncate_ragged_lines = truncate_ragged_lines;
+        self
+    }
+
+    pub fn with_columns(mut self, columns: Option<Vec<(String, DataType)>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn with_infer_schema_length(mut self, infer_schema_length: usize) -> Self {
+        self.infer_schema_length = Some(infer_schema_length);
+        self
+    }
+
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// The options of `read_csv(source, delim => '|', header => false, ...)`.
+    /// `nullstr` is a null value, `nullstrs` lists several separated by
+    /// commas, e.g. `'NA,-'`, `schema` names a file of column definitions.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(
+            1,
+            &[
+                "source",
+                "delim",
+                "header",
+                "quote",
+                "escape",
+                "comment",
+                "skip",
+                "nullstr",
+                "nullstrs",
+                "missing_is_null",
+                "decimal_comma",
+                "truncate_ragged_lines",
+                "columns",
+                "schema",
+                "sample_size",
+                "lenient",
+            ],
+        )?;
+        let mut options = CsvOptions::default();
+        if let Some(delim) = func.string(usize::MAX, "delim")? {
+            options.delimiter = Some(parse_char(&delim, "delim")?);
+        }
+        if let Some(quote) = func.string(usize::MAX, "quote")? {
+            options.quote = Some(parse_char(&quote, "quote")?);
+        }
+        if let Some(escape) = func.string(usize::MAX, "escape")? {
+            options.escape = Some(parse_char(&escape, "escape")?);
+        }
+        options.comment_prefix = func.string(usize::MAX, "comment")?;
+        options.has_header = func.boolean("header")?;
+        options.skip_rows = func.integer("skip")?.unwrap_or(0);
+        options
+            .null_values
+            .extend(func.string(usize::MAX, "nullstr")?);
+        if let Some(nullstrs) = func.string(usize::MAX, "nullstrs")? {
+            let values = nullstrs.split(',').map(|v| v.trim().to_string());
+            options.null_values.extend(values);
+        }
+        options.missing_is_null = func.boolean("missing_is_null")?;
+        options.decimal_comma = func.boolean("decimal_comma")?.unwrap_or(false);
+        options.truncate_ragged_lines = func.boolean("truncate_ragged_lines")?.unwrap_or(false);
+        options.columns = function_columns(func)?;
+        options.infer_schema_length = function_sample_size(func)?;
+        options.lenient = func.boolean("lenient")?.unwrap_or(false);
+        Ok(options)
+    }
+}
+
+impl JsonOptions {
+    pub fn with_records_path(mut self, records_path: Option<String>) -> Self {
+        self.records_path = records_path;
+        self
+    }
+
+    pub fn with_flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    pub fn with_columns(mut self, columns: Option<Vec<(String, DataType)>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn with_infer_schema_length(mut self, infer_schema_length: usize) -> Self {
+        self.infer_schema_length = Some(infer_schema_length);
+        self
+    }
+
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// The options of `read_json(source, records_path => 'data.items',
+    /// flatten => true, ignore_errors => true, columns => 'ts TIMESTAMP',
+    /// sample_size => 'all', lenient => true)`.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(
+            1,
+            &[
+                "source",
+                "records_path",
+                "flatten",
+                "ignore_errors",
+                "columns",
+                "schema",
+                "sample_size",
+                "lenient",
+            ],
+        )?;
+        Ok(JsonOptions {
+            records_path: func.string(usize::MAX, "records_path")?,
+            flatten: func.boolean("flatten")?.unwrap_or(false),
+            ignore_errors: func.boolean("ignore_errors")?.unwrap_or(false),
+            columns: function_columns(func)?,
+            infer_schema_length: function_sample_size(func)?,
+            lenient: func.boolean("lenient")?.unwrap_or(false),
+        })
+    }
+}
+
+/// The `columns` of a table function, after those of its `schema` file.
+fn function_columns(func: &TableFunction) -> Result<Option<Vec<(String, DataType)>>> {
+    let mut columns = match func.string(usize::MAX, "schema")? {
+        Some(path) => Some(read_schema(&path)?),
+        None => None,
+    };
+    if let Some(spec) = func.string(usize::MAX, "columns")? {
+        columns
+            .get_or_insert_with(Vec::new)
+            .extend(parse_columns(&spec)?);
+    }
+    Ok(columns)
+}
+
+/// The `sample_size` of a table function, a number of rows or `'all'`.
+fn function_sample_size(func: &TableFunction) -> Result<Option<usize>> {
+    if let Ok(Some(size)) = func.string(usize::MAX, "sample_size") {
+        return parse_sample_size(&size).map(Some);
+    }
+    match func.integer("sample_size")? {
+        Some(size) => parse_sample_size(&size.to_string()).map(Some),
+        None => Ok(None),
+    }
+}
+
+impl ExcelOptions {
+    pub fn with_sheet(mut self, sheet: Option<String>) -> Self {
+        self.sheet = sheet;
+        self
+    }
+
+    pub fn with_range(mut self, range: Option<String>) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn with_header(mut self, has_header: bool) -> Self {
+        self.has_header = Some(has_header);
+        self
+    }
+
+    /// The options of `read_excel(source, sheet => 'Sales', range => 'B3:F100',
+    /// header => false)`.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(1, &["source", "sheet", "range", "header"])?;
+        Ok(ExcelOptions {
+            sheet: func.string(usize::MAX, "sheet")?,
+            range: func.string(usize::MAX, "range")?,
+            has_header: func.boolean("header")?,
+        })
+    }
+}
+
+impl FwfOptions {
+    pub fn with_columns(mut self, columns: Option<Vec<(String, usize, usize)>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn with_header(mut self, has_header: bool) -> Self {
+        self.has_header = Some(has_header);
+        self
+    }
+
+    pub fn with_skip_rows(mut self, skip_rows: usize) -> Self {
+        self.skip_rows = skip_rows;
+        self
+    }
+
+    /// The options of `read_fwf(source, columns => 'id:1:5, name:6:20',
+    /// header => false, skip => 2)`.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(1, &["source", "columns", "header", "skip"])?;
+        let columns = match func.string(usize::MAX, "columns")? {
+            Some(columns) => Some(parse_fwf_columns(&columns)?),
+            None => None,
+        };
+        Ok(FwfOptions {
+            columns,
+            has_header: func.boolean("header")?,
+            skip_rows: func.integer("skip")?.unwrap_or(0),
+        })
+    }
+}
+
+/// Parse fixed-width columns such as `id:1:5, name:6:20`, each
+/// `name:start:width` with the start counted from 1.
+pub fn parse_fwf_columns(spec: &str) -> Result<Vec<(String, usize, usize)>> {
+    spec.split(',')
+        .map(|column| {
+            let column = column.trim();
+            let parts: Vec<_> = column.rsplitn(3, ':').collect();
+            let [width, start, name] = parts[..] else {
+                return Err(anyhow!("Expected `name:start:width`, got {:?}", column));
+            };
+            let number = |v: &str| v.trim().parse::<usize>().ok().filter(|n| *n > 0);
+            match (number(start), number(width)) {
+                (Some(start), Some(width)) => Ok((name.trim().to_string(), start, width)),
+                _ => Err(anyhow!(
+                    "Start and width of {:?} must be positive integers",
+                    column
+                )),
+            }
+        })
+        .collect()
+}
+
+/// A single byte character, `\t` standing for a tab.
+pub fn parse_char(value: &str, name: &str) -> Result<u8> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        v if v.len() == 1 => Ok(v.as_bytes()[0]),
+        v => Err(anyhow!("{} must be a single character, got {:?}", name, v)),
+    }
+}
+
+/// Rows sampled to infer column types, a positive number or `all` for
+/// every row, read as `usize::MAX`.
+pub fn parse_sample_size(value: &str) -> Result<usize> {
+    match value.trim() {
+        v if v.eq_ignore_ascii_case("all") => Ok(usize::MAX),
+        v => v
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("Sample size must be a positive number or all, got {}", v)),
+    }
+}
+
+/// Parse column definitions such as `id BIGINT, price DECIMAL(10, 2)`, also
+/// accepted as `{id: 'BIGINT', name: 'VARCHAR'}` or a definition per line.
+pub fn parse_columns(spec: &str) -> Result<Vec<(String, DataType)>> {
+    let spec = spec.trim();
+    let spec = spec
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or(spec);
+    // the precision of DECIMAL(10, 2) or length of VARCHAR(20) changes no type
+    let params = regex::Regex::new(r"\(\s*\d+\s*(,\s*\d+\s*)?\)")?;
+    let spec = params.replace_all(spec, "");
+    spec.split([',', '\n'])
+        .filter(|column| !column.trim().is_empty())
+        .map(|column| {
+            let column = column.trim().replace(':', " ");
+            let (name, dtype) = column
+                .rsplit_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("Expected `name TYPE`, got {:?}", column))?;
+            let name = name.trim().trim_matches(['\'', '"']);
+            Ok((
+                name.to_string(),
+                parse_type(dtype.trim_matches(['\'', '"']))?,
+            ))
+        })
+        .collect()
+}
+
+/// Read column definitions from a file: a `.json` file holds an object of
+/// names and types, e.g. `{"id": "BIGINT", "ts": "TIMESTAMP"}`, any other
+/// is written as for `parse_columns`. Errors show no content of the file.
+pub fn read_schema(path: &str) -> Result<Vec<(String, DataType)>> {
+    let path = path.strip_prefix("file://").unwrap_or(path);
+    let spec = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read schema {}: {}", path, e))?;
+    if !path.to_lowercase().ends_with(".json") {
+        return parse_columns(&spec).map_err(|_| {
+            anyhow!(
+                "Invalid schema {}, expected `name TYPE` definitions separated by commas or lines",
+                path
+            )
+        });
+    }
+
+    let SchemaColumns(columns) =
+        serde_json::from_str(&spec).map_err(|e| anyhow!("Invalid schema {}: {}", path, e))?;
+    columns
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, dtype))| {
+            let dtype = parse_type(&dtype)
+                .map_err(|_| anyhow!("Unknown type of column {} in schema {}", i + 1, path))?;
+            Ok((name, dtype))
+        })
+        .collect()
+}
+
+/// The names and types of a JSON schema file, in the order written.
+struct SchemaColumns(Vec<(String, String)>);
+
+impl<'de> serde::Deserialize<'de> for SchemaColumns {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Columns;
+
+        impl<'de> serde::de::Visitor<'de> for Columns {
+            type Value = SchemaColumns;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an object of column names and types")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<SchemaColumns, A::Error> {
+                let mut columns = Vec::new();
+                while let Some(column) = map.next_entry()? {
+                    columns.push(column);
+                }
+                Ok(SchemaColumns(columns))
+            }
+        }
+
+        deserializer.deserialize_map(Columns)
+    }
+}
+
+fn parse_type(name: &str) -> Result<DataType> {
+    let name = match name.trim().split_once('(') {
+        Some((base, params)) if params.ends_with(')') => base,
+        _ => name,
+    };
+    match name.trim().to_uppercase().as_str() {
+        "BOOL" | "BOOLEAN" => Ok(DataType::Boolean),
+        "TINYINT" | "INT8" => Ok(DataType::Int8),
+        "SMALLINT" | "INT16" => Ok(DataType::Int16),
+        "INT" | "INTEGER" | "INT32" => Ok(DataType::Int32),
+        "BIGINT" | "INT64" | "LONG" => Ok(DataType::Int64),
+        "REAL" | "FLOAT" | "FLOAT32" => Ok(DataType::Float32),
+        "DOUBLE" | "FLOAT64" | "DECIMAL" | "NUMERIC" => Ok(DataType::Float64),
+        "VARCHAR" | "TEXT" | "STRING" => Ok(DataType::String),
+        "DATE" => Ok(DataType::Date),
+        "TIMESTAMP" | "DATETIME" => Ok(DataType::Datetime(TimeUnit::Microseconds, None)),
+        v => Err(anyhow!("Unknown column type {}", v)),
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "parquet" => Ok(Format::Parquet),
+            "excel" | "xlsx" | "xls" | "ods" => Ok(Format::Excel),
+            "arrow" | "ipc" | "feather" => Ok(Format::Arrow),
+            "avro" => Ok(Format::Avro),
+            "access_log" | "combined" | "clf" => Ok(Format::AccessLog),
+            "syslog" => Ok(Format::Syslog),
+            "logfmt" => Ok(Format::Logfmt),
+            "xml" => Ok(Format::Xml),
+            "html" | "htm" => Ok(Format::Html),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "fwf" | "fixed_width" => Ok(Format::Fwf),
+            v => Err(anyhow!(
+                "Unknown format {}, expected csv, json, ndjson, parquet, excel, arrow, avro, \
+                 xml, html, yaml, toml, fwf, access_log, syslog or logfmt",
+                v
+            )),
         }
     }
 }
 
-pub fn detect_content(data: String) -> Loader {
-    // Try to detect JSON by checking if it starts with { or [
-    let trimmed = data.trim();
-    if (trimmed.starts_with('[') && trimmed.ends_with(']'))
-        || (trimmed.starts_with('{') && trimmed.ends_with('}'))
+/// The value at a `path` such as `data.items`, `$.a.0.b` or
+/// `$.a[0]['b']`.
+pub(crate) fn select<'a>(
+    value: &'a serde_json::Value,
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    let path = path.replace('[', ".").replace(']', "");
+    let path = path.trim_start_matches('$').trim_start_matches('.');
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |v, key| match v {
+        serde_json::Value::Object(map) => map.get(key.trim_matches(['\'', '"'])),
+        serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// A loader for data read from `location`, its format told, in order, by
+/// its magic bytes, the format declared by the source, e.g. the
+/// `Content-Type` of an HTTP response, the extension of `location`, or its
+/// content.
+pub fn detect_content(
+    location: &str,
+    declared: Option<Format>,
+    data: Vec<u8>,
+    options: &QueryOptions,
+) -> Loader {
+    let (format, by) = if let Some(format) = magic_format(&data) {
+        (format, "magic bytes")
+    } else if let Some(format) = declared {
+        (format, "declared type")
+    } else if let Some(format) = Format::from_extension(location) {
+        (format, "extension")
+    } else {
+        (sniff_format(&data), "content")
+    };
+    tracing::debug!("reading {} as {:?}, told by its {}", location, format, by);
+    format.loader(data, options)
+}
+
+/// The format of binary data starting with a signature.
+pub(crate) fn magic_format(data: &[u8]) -> Option<Format> {
+    if data.starts_with(b"PAR1") {
+        Some(Format::Parquet)
+    } else if data.starts_with(b"ARROW1") || data.starts_with(&IPC_CONTINUATION) {
+        Some(Format::Arrow)
+    } else if data.starts_with(b"Obj\x01") {
+        Some(Format::Avro)
+    } else if is_spreadsheet(data) {
+        Some(Format::Excel)
+    } else {
+        None
+    }
+}
+
+/// The format of text data by its first characters and lines, CSV unless
+/// it looks like JSON, JSON Lines, YAML or markup.
+fn sniff_format(data: &[u8]) -> Format {
+    let trimmed = data.trim_ascii();
+    if is_json_lines(data) {
+        Format::Ndjson
+    } else if (trimmed.starts_with(b"[") && trimmed.ends_with(b"]"))
+        || (trimmed.starts_with(b"{") && trimmed.ends_with(b"}"))
     {
-        Loader::Json(JsonLoader(data))
+        Format::Json
+    } else if data.starts_with(b"---") {
+        Format::Yaml
+    } else if trimmed.starts_with(b"<") {
+        match is_html(trimmed) {
+            true => Format::Html,
+            false => Format::Xml,
+        }
     } else {
-        Loader::Csv(CsvLoader(data))
+        Format::Csv
+    }
+}
+
+/// The delimiter of CSV data among `,`, `;`, tab and `|`: the one found
+/// the same number of times on each of the first lines, the most frequent
+/// one when none is. Quoted text and comments are not counted.
+pub(crate) fn sniff_delimiter(data: &[u8], options: &CsvOptions) -> u8 {
+    const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+    let comment = options.comment_prefix.as_deref().unwrap_or_default();
+    let quote = options.quote.unwrap_or(b'"');
+    let lines: Vec<[usize; 4]> = data
+        .split(|&b| b == b'\n')
+        .skip(options.skip_rows)
+        .filter(|line| !line.trim_ascii().is_empty())
+        .filter(|line| comment.is_empty() || !line.starts_with(comment.as_bytes()))
+        .take(20)
+        .map(|line| {
+            let mut counts = [0; 4];
+            let mut quoted = false;
+            for &b in line {
+                match b {
+                    b if b == quote => quoted = !quoted,
+                    b if !quoted => {
+                        if let Some(i) = DELIMITERS.iter().position(|&d| d == b) {
+                            counts[i] += 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            counts
+        })
+        .collect();
+    let Some(first) = lines.first() else {
+        return b',';
+    };
+
+    let consistent = (0..DELIMITERS.len())
+        .filter(|&i| first[i] > 0 && lines.iter().all(|counts| counts[i] == first[i]))
+        .max_by_key(|&i| (first[i], std::cmp::Reverse(i)));
+    let frequent = (0..DELIMITERS.len())
+        .filter(|&i| first[i] > 0)
+        .max_by_key(|&i| (first[i], std::cmp::Reverse(i)));
+    consistent
+        .or(frequent)
+        .map(|i| DELIMITERS[i])
+        .unwrap_or(b',')
+}
+
+/// Whether markup is an HTML page or fragment rather than XML, by its
+/// doctype or first elements.
+fn is_html(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_lowercase();
+    head.starts_with("<!doctype html")
+        || head.contains("<html")
+        || (!head.starts_with("<?xml") && head.contains("<table"))
+}
+
+/// Marker starting every message of an Arrow IPC stream.
+const IPC_CONTINUATION: [u8; 4] = [0xff; 4];
+
+/// Whether `data` is a spreadsheet: an OLE compound file (xls) or a zip
+/// archive holding a workbook (xlsx, xlsb, ods).
+pub(crate) fn is_spreadsheet(data: &[u8]) -> bool {
+    if data.starts_with(&[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1]) {
+        return true;
+    }
+    if !data.starts_with(b"PK\x03\x04") {
+        return false;
+    }
+    let Ok(mut archive) = ::zip::ZipArchive::new(Cursor::new(data)) else {
+        return false;
+    };
+    if archive.index_for_name("xl/workbook.xml").is_some()
+        || archive.index_for_name("xl/workbook.bin").is_some()
+    {
+        return true;
     }
+    let mut mimetype = String::new();
+    archive
+        .by_name("mimetype")
+        .and_then(|mut f| Ok(f.read_to_string(&mut mimetype)?))
+        .is_ok_and(|_| mimetype.starts_with("application/vnd.oasis.opendocument.spreadsheet"))
+}
+
+/// Whether `data` holds JSON objects one per line: its first line is a
+/// complete object and more data follows.
+pub(crate) fn is_json_lines(data: &[u8]) -> bool {
+    let trimmed = data.trim_ascii_start();
+    let Some(end) = trimmed.iter().position(|&b| b == b'\n') else {
+        return false;
+    };
+    let (first, rest) = trimmed.split_at(end);
+    let first = first.trim_ascii();
+    first.starts_with(b"{")
+        && !rest.trim_ascii().is_empty()
+        && serde_json::from_slice::<serde::de::IgnoredAny>(first).is_ok()
 }
 
 impl Load for CsvLoader {
     type Error = anyhow::Error;
 
     fn load(self) -> Result<DataSet, Self::Error> {
-        let cursor = Cursor::new(self.0.as_bytes());
-        let df = CsvReadOptions::default()
-            .with_infer_schema_length(Some(16))
-            .into_reader_with_file_handle(cursor)
-            .finish()?;
+        let CsvLoader(mut data, options) = self;
+        let has_header = options.has_header.unwrap_or(true);
+        let quote = options.quote.unwrap_or(b'"');
+        if let Some(escape) = options.escape.filter(|&e| e != quote) {
+            data = unescape(&data, escape, quote);
+        }
+        let delimiter = options.delimiter.unwrap_or_else(|| {
+            let delimiter = sniff_delimiter(&data, &options);
+            tracing::debug!("reading CSV delimited by {:?}", delimiter as char);
+            delimiter
+        });
+        let declared = options.columns.clone().unwrap_or_default();
+        let schema = options
+            .columns
+            .map(|columns| Schema::from_iter(columns.into_iter().map(|(n, t)| Field::new(&n, t))));
+        let null_values = match options.null_values.len() {
+            0 => None,
+            _ => Some(NullValues::AllColumns(options.null_values)),
+        };
+
+        let read = |schema: Option<Schema>, infer_schema_length, ignore_errors| {
+            let schema = schema.map(Arc::new);
+            let mut reader = CsvReadOptions::default()
+                .with_infer_schema_length(Some(infer_schema_length))
+                .with_ignore_errors(ignore_errors)
+                .with_has_header(has_header)
+                .with_skip_rows(options.skip_rows)
+                .map_parse_options(|parse| {
+                    parse
+                        .with_separator(delimiter)
+                        .with_quote_char(Some(quote))
+                        .with_comment_prefix(options.comment_prefix.as_deref())
+                        .with_null_values(null_values.clone())
+                        .with_missing_is_null(options.missing_is_null.unwrap_or(true))
+                        .with_decimal_comma(options.decimal_comma)
+                        .with_truncate_ragged_lines(options.truncate_ragged_lines)
+                });
+            reader = match has_header {
+                true => reader.with_schema_overwrite(schema),
+                false => reader.with_schema(schema),
+            };
+            reader
+                .into_reader_with_file_handle(Cursor::new(data.as_slice()))
+                .finish()
+        };
+
+        let infer_schema_length = options.infer_schema_length.unwrap_or(16);
+        if options.lenient {
+            // every column is read as strings and then converted, so that
+            // the values which do not convert are counted
+            let strings = schema.map(|schema| {
+                Schema::from_iter(schema.iter_names().map(|n| Field::new(n, DataType::String)))
+            });
+            let mut df = read(strings, 0, false)?;
+            let mut columns = declared;
+            for series in df.get_columns() {
+                if !columns.iter().any(|(name, _)| name == series.name()) {
+                    let dtype = infer_type(series, infer_schema_length, options.decimal_comma)?;
+                    columns.push((series.name().to_string(), dtype));
+                }
+            }
+            if options.decimal_comma {
+                for (name, _) in columns.iter().filter(|(_, dtype)| dtype.is_float()) {
+                    let points: StringChunked = df
+                        .column(name)?
+                        .str()?
+                        .into_iter()
+                        .map(|v| v.map(|v| v.replace(',', ".")))
+                        .collect();
+                    df.with_column(points.with_name(name).into_series())?;
+                }
+            }
+            return Ok(DataSet(cast_columns(df, &columns, true)?));
+        }
 
+        let df = read(schema, infer_schema_length, false).map_err(|e| match e {
+            PolarsError::ComputeError(msg) if msg.starts_with("could not parse") => anyhow!(
+                "{}; infer the types from more rows with sample_size, declare them \
+                 with columns or read such values as null with lenient",
+                msg.lines().next().unwrap_or_default()
+            ),
+            e => e.into(),
+        })?;
         Ok(DataSet(df))
     }
 }
 
+/// The type of a column of strings as its first `sample` values tell:
+/// integer, float, boolean or else string, as the CSV reader infers it.
+fn infer_type(series: &Series, sample: usize, decimal_comma: bool) -> Result<DataType> {
+    let values: Vec<&str> = series.str()?.into_iter().take(sample).flatten().collect();
+    let all = |parses: fn(&str, bool) -> bool| {
+        !values.is_empty() && values.iter().all(|v| parses(v, decimal_comma))
+    };
+    Ok(if all(|v, _| v.parse::<i64>().is_ok()) {
+        DataType::Int64
+    } else if all(|v, comma| match comma {
+        true => v.replace(',', ".").parse::<f64>().is_ok(),
+        false => v.parse::<f64>().is_ok(),
+    }) {
+        DataType::Float64
+    } else if all(|v, _| v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false")) {
+        DataType::Boolean
+    } else {
+        DataType::String
+    })
+}
+
+/// Warn how many values of each column were read as null as they did not
+/// parse as its type.
+fn report_coerced(coerced: &[(String, usize)]) {
+    if coerced.is_empty() {
+        return;
+    }
+    let total: usize = coerced.iter().map(|(_, count)| count).sum();
+    let columns: Vec<_> = coerced
+        .iter()
+        .map(|(name, count)| format!("{} ({})", name, count))
+        .collect();
+    tracing::warn!(
+        "read {} unparsable values as null: {}",
+        total,
+        columns.join(", ")
+    );
+}
+
+/// Convert the named columns of `df` to their types. Values that do not
+/// convert fail the load, or are null when `lenient`.
+fn cast_columns(
+    mut df: DataFrame,
+    columns: &[(String, DataType)],
+    lenient: bool,
+) -> Result<DataFrame> {
+    let mut coerced = Vec::new();
+    for (name, dtype) in columns {
+        let Ok(series) = df.column(name) else {
+            continue;
+        };
+        let cast = cast_series(series, dtype)?;
+        let failed = &cast.is_null() & &series.is_not_null();
+        let count = failed.sum().unwrap_or(0) as usize;
+        if count > 0 && !lenient {
+            let value = series.filter(&failed)?.get(0)?.to_string();
+            return Err(anyhow!(
+                "{} values of column {} are not {}, e.g. {}; read them as null with lenient",
+                count,
+                name,
+                dtype,
+                value
+            ));
+        }
+        if count > 0 {
+            coerced.push((name.clone(), count));
+        }
+        df.with_column(cast)?;
+    }
+    report_coerced(&coerced);
+    Ok(df)
+}
+
+/// `series` as `dtype`, null where a value does not convert. Strings are
+/// read as dates and datetimes in RFC 3339 or `2024-01-31 12:00:00` form.
+fn cast_series(series: &Series, dtype: &DataType) -> Result<Series> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+    fn micros(v: &str) -> Option<i64> {
+        let datetime = DateTime::parse_from_rfc3339(v)
+            .map(|t| t.naive_utc())
+            .or_else(|_| NaiveDateTime::parse_from_str(v, "%Y-%m-%d %H:%M:%S%.f"))
+            .or_else(|_| NaiveDateTime::parse_from_str(v, "%Y-%m-%dT%H:%M:%S%.f"))
+            .or_else(|_| NaiveDate::parse_from_str(v, "%Y-%m-%d").map(|d| d.into()))
+            .ok()?;
+        Some(datetime.and_utc().timestamp_micros())
+    }
+
+    match (series.dtype(), dtype) {
+        (DataType::String, DataType::Boolean) => {
+            let values: BooleanChunked = series
+                .str()?
+                .into_iter()
+                .map(|v| match v?.to_lowercase().as_str() {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => None,
+                })
+                .collect();
+            Ok(values.with_name(series.name()).into_series())
+        }
+        (DataType::String, DataType::Date | DataType::Datetime(..)) => {
+            let values: Vec<_> = series
+                .str()?
+                .into_iter()
+                .map(|v| v.and_then(micros))
+                .collect();
+            let datetimes = Series::new(series.name(), values)
+                .cast(&DataType::Datetime(TimeUnit::Microseconds, None))?;
+            Ok(datetimes.cast(dtype)?)
+        }
+        _ => Ok(series.cast(dtype)?),
+    }
+}
+
+/// Rewrite quotes escaped by `escape` inside quoted fields as doubled
+/// quotes, and other escaped characters as themselves.
+fn unescape(data: &[u8], escape: u8, quote: u8) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(data.len());
+    let mut quoted = false;
+    let mut bytes = data.iter().copied();
+    while let Some(b) = bytes.next() {
+        match b {
+            b if b == escape && quoted => match bytes.next() {
+                Some(c) if c == quote => unescaped.extend([quote, quote]),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push(b),
+            },
+            b => {
+                quoted ^= b == quote;
+                unescaped.push(b);
+            }
+        }
+    }
+    unescaped
+}
+
 impl Load for JsonLoader {
     type Error = anyhow::Error;
 
     fn load(self) -> Result<DataSet, Self::Error> {
-        let cursor = Cursor::new(self.0.as_bytes());
+        let JsonLoader(mut data, options) = self;
+        if is_json_lines(&data) {
+            return NdjsonLoader(data, options).load();
+        }
+        if let Some(path) = &options.records_path {
+            let value: serde_json::Value = serde_json::from_slice(&data)
+                .map_err(|e| anyhow!("Failed to parse JSON: {}", e))?;
+            let records = select(&value, path)
+                .ok_or_else(|| anyhow!("No records at {} in the JSON", path))?;
+            data = serde_json::to_vec(records)?;
+        }
+
+        let cursor = Cursor::new(data);
         let df = JsonReader::new(cursor)
-            .infer_schema_len(NonZero::new(100))
+            .infer_schema_len(NonZero::new(options.infer_schema_length.unwrap_or(100)))
             .finish()
             .map_err(|e| anyhow!("Failed to parse JSON: {}", e))?;
 
+        json_columns(df, &options)
+    }
+}
+
+impl Load for NdjsonLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let NdjsonLoader(data, options) = self;
+        if options.records_path.is_some() {
+            return Err(anyhow!(
+                "records_path is not supported for newline-delimited JSON, \
+                 every line is a record"
+            ));
+        }
+        let read = |data: &[u8]| {
+            JsonLineReader::new(Cursor::new(data))
+                .infer_schema_len(NonZero::new(options.infer_schema_length.unwrap_or(100)))
+                .finish()
+        };
+        // lines are only checked one by one to tell which of them failed
+        let df = match read(&data) {
+            Ok(df) => df,
+            Err(e) => match check_lines(&data, options.ignore_errors)? {
+                Some(kept) => read(&kept),
+                None => Err(e),
+            }
+            .map_err(|e| anyhow!("Failed to parse JSON Lines: {}", e))?,
+        };
+
+        json_columns(df, &options)
+    }
+}
+
+/// The columns of JSON records, flattened and of the declared types as
+/// the options tell.
+fn json_columns(df: DataFrame, options: &JsonOptions) -> Result<DataSet> {
+    let df = match options.flatten {
+        true => flatten(df)?,
+        false => df,
+    };
+    match &options.columns {
+        Some(columns) => Ok(DataSet(cast_columns(df, columns, options.lenient)?)),
+        None => Ok(DataSet(df)),
+    }
+}
+
+/// Replace struct columns by a column per field, named `parent.field`,
+/// recursively.
+pub(crate) fn flatten(df: DataFrame) -> Result<DataFrame> {
+    fn push(series: &Series, columns: &mut Vec<Series>) -> Result<()> {
+        match series.dtype() {
+            DataType::Struct(_) => {
+                for field in series.struct_()?.fields() {
+                    let mut field = field.clone();
+                    field.rename(&format!("{}.{}", series.name(), field.name()));
+                    push(&field, columns)?;
+                }
+            }
+            _ => columns.push(series.clone()),
+        }
+        Ok(())
+    }
+
+    let mut columns = Vec::with_capacity(df.width());
+    for series in df.get_columns() {
+        push(series, &mut columns)?;
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Validate every line of newline-delimited JSON. Malformed lines fail the
+/// load with their line number, or are dropped when `ignore_errors` is set,
+/// giving the lines kept. `None` when every line is well-formed.
+fn check_lines(data: &[u8], ignore_errors: bool) -> Result<Option<Vec<u8>>> {
+    let mut malformed = Vec::new();
+    for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            continue;
+        }
+        if let Err(e) = serde_json::from_slice::<serde::de::IgnoredAny>(line) {
+            if !ignore_errors {
+                return Err(anyhow!("Malformed JSON on line {}: {}", i + 1, e));
+            }
+            malformed.push(i);
+        }
+    }
+    if malformed.is_empty() {
+        return Ok(None);
+    }
+
+    let shown: Vec<_> = malformed
+        .iter()
+        .take(10)
+        .map(|i| (i + 1).to_string())
+        .collect();
+    tracing::warn!(
+        "skipped {} malformed JSON lines: {}{}",
+        malformed.len(),
+        shown.join(", "),
+        if malformed.len() > shown.len() {
+            ", ..."
+        } else {
+            ""
+        }
+    );
+    let mut malformed = malformed.into_iter().peekable();
+    let mut kept = Vec::with_capacity(data.len());
+    for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+        if malformed.next_if_eq(&i).is_none() {
+            kept.extend_from_slice(line);
+            kept.push(b'\n');
+        }
+    }
+    Ok(Some(kept))
+}
+
+impl Load for FwfLoader {
+    type Error = anyhow::Error;
+
+    /// The fields are cut from each line, trimmed and read as CSV, so that
+    /// their types are inferred as they are for CSV. Blank lines are skipped
+    /// and short lines have null trailing fields.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let FwfLoader(data, options) = self;
+        let text = String::from_utf8_lossy(&data);
+        let mut lines = text
+            .lines()
+            .skip(options.skip_rows)
+            .filter(|line| !line.trim().is_empty());
+
+        let header = match options.has_header.unwrap_or(options.columns.is_none()) {
+            true => lines.next(),
+            false => None,
+        };
+        let columns = match (options.columns, header) {
+            (Some(columns), _) => columns,
+            (None, Some(header)) => header_columns(header),
+            (None, None) => {
+                return Err(anyhow!(
+                    "Fixed-width data needs columns, e.g. `id:1:5, name:6:20`, or a header line"
+                ))
+            }
+        };
+        // columns given through `FwfOptions` skip `parse_fwf_columns`
+        if let Some((name, _, _)) = columns.iter().find(|(_, start, _)| *start == 0) {
+            return Err(anyhow!("Column {} must start at 1 or later", name));
+        }
+
+        let mut csv = String::new();
+        let names = columns.iter().map(|(name, _, _)| csv_field(name));
+        csv.push_str(&names.collect::<Vec<_>>().join(","));
+        csv.push('\n');
+        for line in lines {
+            let chars: Vec<char> = line.chars().collect();
+            let fields = columns.iter().map(|(_, start, width)| {
+                let start = (start - 1).min(chars.len());
+                let end = start.saturating_add(*width).min(chars.len());
+                let field: String = chars[start..end].iter().collect();
+                csv_field(field.trim())
+            });
+            csv.push_str(&fields.collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        }
+        CsvLoader(csv.into_bytes(), CsvOptions::default().with_delimiter(b',')).load()
+    }
+}
+
+/// Columns starting at each name of a header line and ending where the
+/// next one starts, the last one at the end of the line.
+fn header_columns(header: &str) -> Vec<(String, usize, usize)> {
+    let mut columns: Vec<(String, usize, usize)> = Vec::new();
+    let mut name = String::new();
+    for (i, c) in header.chars().chain([' ']).enumerate() {
+        if !c.is_whitespace() {
+            name.push(c);
+            continue;
+        }
+        if name.is_empty() {
+            continue;
+        }
+        let start = i - name.chars().count() + 1;
+        if let Some(last) = columns.last_mut() {
+            last.2 = start - last.1;
+        }
+        columns.push((std::mem::take(&mut name), start, usize::MAX));
+    }
+    columns
+}
+
+/// A CSV field, quoted when needed. Empty fields are left empty, read as
+/// null.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+impl Load for YamlLoader {
+    type Error = anyhow::Error;
+
+    /// The documents of the stream are the rows, or the items of a single
+    /// document holding a list.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        use serde::Deserialize;
+
+        let YamlLoader(data, options) = self;
+        let mut documents = Vec::new();
+        for (i, document) in serde_yaml::Deserializer::from_slice(&data).enumerate() {
+            let value = serde_json::Value::deserialize(document)
+                .map_err(|e| anyhow!("Failed to parse YAML document {}: {}", i + 1, e))?;
+            // empty documents, e.g. after a trailing `---`
+            if !value.is_null() {
+                documents.push(value);
+            }
+        }
+        let value = match documents.len() {
+            1 => documents.remove(0),
+            _ => serde_json::Value::Array(documents),
+        };
+        load_value(value, options)
+    }
+}
+
+impl Load for TomlLoader {
+    type Error = anyhow::Error;
+
+    /// The document is a row, unless it holds a single array of tables,
+    /// e.g. the `[[package]]` of a Cargo.lock, whose tables are the rows.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let TomlLoader(data, options) = self;
+        let text = std::str::from_utf8(&data).map_err(|e| anyhow!("TOML is not UTF-8: {}", e))?;
+        let table: toml::Table =
+            toml::from_str(text).map_err(|e| anyhow!("Failed to parse TOML: {}", e))?;
+        let value = toml_to_json(toml::Value::Table(table));
+
+        let mut arrays = value
+            .as_object()
+            .into_iter()
+            .flat_map(|table| table.values())
+            .filter(|v| {
+                v.as_array()
+                    .is_some_and(|a| !a.is_empty() && a.iter().all(|i| i.is_object()))
+            });
+        let value = match (options.records_path.is_none(), arrays.next(), arrays.next()) {
+            (true, Some(array), None) => array.clone(),
+            _ => value,
+        };
+        load_value(value, options)
+    }
+}
+
+/// Load parsed YAML or TOML as JSON: a list is a row per item, anything
+/// else a single row.
+fn load_value(value: serde_json::Value, options: JsonOptions) -> Result<DataSet> {
+    let value = match (&options.records_path, value) {
+        (None, value @ serde_json::Value::Array(_)) => value,
+        (None, value) => serde_json::Value::Array(vec![value]),
+        (Some(_), value) => value,
+    };
+    JsonLoader(serde_json::to_vec(&value)?, options).load()
+}
+
+/// TOML values as JSON, datetimes as RFC 3339 strings.
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(n) => n.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(d) => serde_json::Value::String(d.to_string()),
+        toml::Value::Array(items) => items.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+impl Load for ParquetLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let df = ParquetReader::new(Cursor::new(self.0))
+            .finish()
+            .map_err(|e| anyhow!("Failed to read Parquet: {}", e))?;
+
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for ExcelLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let ExcelLoader(data, options) = self;
+        let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(data))
+            .map_err(|e| anyhow!("Failed to read spreadsheet: {}", e))?;
+
+        let names = workbook.sheet_names();
+        let sheet = match options.sheet {
+            Some(sheet) if names.contains(&sheet) => sheet,
+            Some(sheet) => {
+                return Err(anyhow!(
+                    "Sheet {} not found, the workbook has: {}",
+                    sheet,
+                    names.join(", ")
+                ))
+            }
+            None => names
+                .first()
+                .cloned()
+                .ok_or_else(|| anyhow!("The workbook has no sheets"))?,
+        };
+        let mut range = workbook
+            .worksheet_range(&sheet)
+            .map_err(|e| anyhow!("Failed to read sheet {}: {}", sheet, e))?;
+        if let Some(spec) = &options.range {
+            // only the used area holds cells, a range past it is empty
+            let (start, end) = parse_range(spec)?;
+            range = match range.end() {
+                Some(last) => {
+                    let end = end.unwrap_or(last);
+                    let end = (end.0.min(last.0), end.1.min(last.1));
+                    match start.0 <= end.0 && start.1 <= end.1 {
+                        true => range.range(start, end),
+                        false => calamine::Range::empty(),
+                    }
+                }
+                None => calamine::Range::empty(),
+            };
+        }
+
+        let mut rows = range.rows();
+        let header = match options.has_header.unwrap_or(true) {
+            true => rows.next().unwrap_or_default(),
+            false => &[],
+        };
+        let mut columns: Vec<Vec<&calamine::Data>> = vec![Vec::new(); range.width()];
+        for row in rows {
+            for (column, cell) in columns.iter_mut().zip(row) {
+                column.push(cell);
+            }
+        }
+
+        let series = columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, cells)| {
+                let name = match header.get(i) {
+                    Some(calamine::Data::Empty) | None => format!("column{}", i + 1),
+                    Some(cell) => cell.to_string(),
+                };
+                cells_to_series(&name, cells)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DataSet(DataFrame::new(series)?))
+    }
+}
+
+impl Load for ArrowLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        // the file format is framed by `ARROW1`, the stream format is not
+        let df = match self.0.starts_with(b"ARROW1") {
+            true => IpcReader::new(Cursor::new(self.0)).finish(),
+            false => IpcStreamReader::new(Cursor::new(self.0)).finish(),
+        }
+        .map_err(|e| anyhow!("Failed to read Arrow IPC: {}", e))?;
+
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for AvroLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let df = polars::io::avro::AvroReader::new(Cursor::new(self.0))
+            .finish()
+            .map_err(|e| anyhow!("Failed to read Avro: {}", e))?;
+
         Ok(DataSet(df))
     }
 }
+
+/// Zero based `(row, column)` position of a cell.
+type Cell = (u32, u32);
+
+/// Columns of a spreadsheet, `A` to `XFD`.
+const MAX_COLUMNS: u32 = 16384;
+
+/// The rows of a sheet, `1048576` being the last.
+const MAX_ROWS: u32 = 1048576;
+
+/// Parse a cell range such as `B3:F100`, or `B3` with no end.
+fn parse_range(spec: &str) -> Result<(Cell, Option<Cell>)> {
+    let cell = |cell: &str| -> Result<Cell> {
+        let cell = cell.trim().to_uppercase();
+        let split = cell
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(cell.len());
+        let (letters, digits) = cell.split_at(split);
+        let row: u32 = digits.parse().unwrap_or(0);
+        if letters.is_empty()
+            || !letters.chars().all(|c| c.is_ascii_uppercase())
+            || row == 0
+            || row > MAX_ROWS
+        {
+            return Err(anyhow!("Invalid cell {} in range {}", cell, spec));
+        }
+        let column = letters
+            .bytes()
+            .try_fold(0u32, |n, c| {
+                n.checked_mul(26)?.checked_add(u32::from(c - b'A' + 1))
+            })
+            .filter(|&n| letters.len() <= 3 && n <= MAX_COLUMNS)
+            .ok_or_else(|| anyhow!("Column {} in range {} is past XFD", letters, spec))?;
+        Ok((row - 1, column - 1))
+    };
+    match spec.split_once(':') {
+        Some((start, end)) => {
+            let (start, end) = (cell(start)?, cell(end)?);
+            if end.0 < start.0 || end.1 < start.1 {
+                return Err(anyhow!("Range {} ends before it starts", spec));
+            }
+            Ok((start, Some(end)))
+        }
+        None => Ok((cell(spec)?, None)),
+    }
+}
+
+/// A column of cells becomes the narrowest type holding its values:
+/// integer, float, boolean, datetime or string. Empty and error cells are
+/// null.
+fn cells_to_series(name: &str, cells: Vec<&calamine::Data>) -> Result<Series> {
+    use calamine::{Data, DataType as _};
+
+    let values: Vec<_> = cells
+        .iter()
+        .filter(|c| !matches!(c, Data::Empty | Data::Error(_)))
+        .collect();
+    let all = |f: fn(&Data) -> bool| values.iter().all(|c| f(c));
+
+    // xlsx stores every number as a float
+    let series = if all(|c| {
+        matches!(c, Data::Int(_)) || matches!(c, Data::Float(f) if f.fract() == 0.0)
+    }) {
+        let ints: Vec<Option<i64>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::Int(n) => Some(*n),
+                Data::Float(f) => Some(*f as i64),
+                _ => None,
+            })
+            .collect();
+        Series::new(name, ints)
+    } else if all(|c| matches!(c, Data::Int(_) | Data::Float(_))) {
+        let floats: Vec<_> = cells.iter().map(|c| c.as_f64()).collect();
+        Series::new(name, floats)
+    } else if all(|c| matches!(c, Data::Bool(_))) {
+        let bools: Vec<_> = cells.iter().map(|c| c.get_bool()).collect();
+        Series::new(name, bools)
+    } else if all(|c| matches!(c, Data::DateTime(d) if d.is_datetime())) {
+        // days since 1899-12-30 to milliseconds since the epoch
+        let millis: Vec<Option<i64>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::DateTime(d) => Some(((d.as_f64() - 25569.0) * 86_400_000.0).round() as i64),
+                _ => None,
+            })
+            .collect();
+        Series::new(name, millis).cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?
+    } else {
+        let strings: Vec<Option<String>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::Empty | Data::Error(_) => None,
+                c => Some(c.to_string()),
+            })
+            .collect();
+        Series::new(name, strings)
+    };
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal xlsx workbook, cells given as raw `<c>` contents by row.
+    fn xlsx(sheets: &[(&str, &[&[&str]])]) -> Vec<u8> {
+        use std::io::Write;
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let mut file = |name: &str, body: String| {
+            zip.start_file(name, ::zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(body.as_bytes()).unwrap();
+        };
+        let ns = "http://schemas.openxmlformats.org";
+        file(
+            "[Content_Types].xml",
+            format!(
+                r#"<Types xmlns="{ns}/package/2006/content-types"><Default Extension="xml" ContentType="application/xml"/></Types>"#
+            ),
+        );
+        file(
+            "_rels/.rels",
+            format!(
+                r#"<Relationships xmlns="{ns}/package/2006/relationships"><Relationship Id="rId1" Type="{ns}/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#
+            ),
+        );
+        let (mut listed, mut rels) = (String::new(), String::new());
+        for (i, (name, rows)) in sheets.iter().enumerate() {
+            let id = i + 1;
+            listed += &format!(r#"<sheet name="{name}" sheetId="{id}" r:id="rId{id}"/>"#);
+            rels += &format!(
+                r#"<Relationship Id="rId{id}" Type="{ns}/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{id}.xml"/>"#
+            );
+            let rows: String = rows
+                .iter()
+                .enumerate()
+                .map(|(r, cells)| {
+                    let cells: String = cells
+                        .iter()
+                        .enumerate()
+                        .map(|(c, cell)| {
+                            format!(r#"<c r="{}{}" {cell}</c>"#, (b'A' + c as u8) as char, r + 1)
+                        })
+                        .collect();
+                    format!(r#"<row r="{}">{cells}</row>"#, r + 1)
+                })
+                .collect();
+            file(
+                &format!("xl/worksheets/sheet{id}.xml"),
+                format!(
+                    r#"<worksheet xmlns="{ns}/spreadsheetml/2006/main"><sheetData>{rows}</sheetData></worksheet>"#
+                ),
+            );
+        }
+        file(
+            "xl/workbook.xml",
+            format!(
+                r#"<workbook xmlns="{ns}/spreadsheetml/2006/main" xmlns:r="{ns}/officeDocument/2006/relationships"><sheets>{listed}</sheets></workbook>"#
+            ),
+        );
+        file(
+            "xl/_rels/workbook.xml.rels",
+            format!(
+                r#"<Relationships xmlns="{ns}/package/2006/relationships">{rels}</Relationships>"#
+            ),
+        );
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn spreadsheets() {
+        let text = |s: &str| format!(r#"t="inlineStr"><is><t>{s}</t></is>"#);
+        let (name, ok, title) = (text("name"), text("ok"), text("report"));
+        let (a, b) = (text("a"), text("b"));
+        let data = xlsx(&[
+            ("Summary", &[&[&title]]),
+            (
+                "Data",
+                &[
+                    &[&title],
+                    &[&name, ">", &ok, ">"],
+                    &[&a, "><v>1</v>", r#"t="b"><v>1</v>"#, "><v>1.5</v>"],
+                    &[&b, "><v>2</v>", r#"t="b"><v>0</v>"#, "><v>3</v>"],
+                ],
+            ),
+        ]);
+        assert!(is_spreadsheet(&data));
+        assert_eq!(
+            Format::from_extension("file:///r.xlsx#Data"),
+            Some(Format::Excel)
+        );
+
+        let options = ExcelOptions::default()
+            .with_sheet(Some("Data".into()))
+            .with_range(Some("A2".into()));
+        let df = ExcelLoader(data.clone(), options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["name", "column2", "ok", "column4"]);
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::String,
+                DataType::Int64,
+                DataType::Boolean,
+                DataType::Float64
+            ]
+        );
+
+        let options = ExcelOptions::default()
+            .with_sheet(Some("Data".into()))
+            .with_range(Some("b3:c4".into()))
+            .with_header(false);
+        let df = ExcelLoader(data.clone(), options).load().unwrap().0;
+        assert_eq!(df.shape(), (2, 2));
+
+        let df = ExcelLoader(data.clone(), ExcelOptions::default())
+            .load()
+            .unwrap()
+            .0;
+        assert_eq!(df.get_column_names(), ["report"]);
+        let options = ExcelOptions::default().with_sheet(Some("Other".into()));
+        let err = ExcelLoader(data, options).load().unwrap_err();
+        assert!(err.to_string().contains("Summary, Data"), "{}", err);
+        assert!(parse_range("A0").is_err());
+        assert_eq!(parse_range("AA10:AB12").unwrap(), ((9, 26), Some((11, 27))));
+        assert_eq!(parse_range("XFD1").unwrap(), ((0, 16383), None));
+        assert!(parse_range("XFE1").is_err());
+        assert!(parse_range("ZZZZZZZZ1").is_err());
+        assert!(parse_range("A1048577").is_err());
+        assert!(parse_range("C5:A1").is_err());
+        assert!(parse_range("B3:A10").is_err());
+    }
+
+    #[test]
+    fn spreadsheet_ranges() {
+        let data = xlsx(&[(
+            "Sheet1",
+            &[&["><v>1</v>", "><v>2</v>"], &["><v>3</v>", "><v>4</v>"]],
+        )]);
+        let load = |range: &str| {
+            let options = ExcelOptions::default()
+                .with_range(Some(range.into()))
+                .with_header(false);
+            ExcelLoader(data.clone(), options)
+                .load()
+                .map(|d| d.0.shape())
+        };
+        // ranges reaching past the used area are clamped to it
+        assert_eq!(load("A1:XFD1048576").unwrap(), (2, 2));
+        assert_eq!(load("B1:C9").unwrap(), (2, 1));
+        assert_eq!(load("Z100").unwrap(), (0, 0));
+        assert_eq!(load("A3:B4").unwrap(), (0, 0));
+        assert!(load("C5:A1").is_err());
+        assert!(load("B3:A10").is_err());
+    }
+
+    #[test]
+    fn arrow_and_avro() {
+        let mut df = df!("id" => [1i64, 2], "name" => ["a", "b"]).unwrap();
+        let options = QueryOptions::default();
+
+        let mut file = Vec::new();
+        IpcWriter::new(&mut file).finish(&mut df).unwrap();
+        let mut stream = Vec::new();
+        IpcStreamWriter::new(&mut stream).finish(&mut df).unwrap();
+        let mut avro = Vec::new();
+        polars::io::avro::AvroWriter::new(&mut avro)
+            .finish(&mut df)
+            .unwrap();
+
+        for (data, format) in [
+            (file, Format::Arrow),
+            (stream, Format::Arrow),
+            (avro, Format::Avro),
+        ] {
+            let loader = detect_content("", None, data, &options);
+            match format {
+                Format::Arrow => assert!(matches!(loader, Loader::Arrow(_))),
+                _ => assert!(matches!(loader, Loader::Avro(_))),
+            }
+            assert!(loader.load().unwrap().0.equals(&df));
+        }
+    }
+
+    #[test]
+    fn lenient_csv() {
+        let data = b"n;f;b;s\n1;1,5;true;x\n2;oops;FALSE;y\nz;2,0;true;\n";
+        let options = CsvOptions::default()
+            .with_decimal_comma(true)
+            .with_infer_schema_length(1)
+            .with_lenient(true);
+        let df = CsvLoader(data.to_vec(), options).load().unwrap().0;
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Boolean,
+                DataType::String
+            ]
+        );
+        let nulls: Vec<_> = df.get_columns().iter().map(|s| s.null_count()).collect();
+        assert_eq!(nulls, [1, 1, 0, 1]);
+        assert_eq!(df.column("f").unwrap().f64().unwrap().get(2), Some(2.0));
+        assert_eq!(df.column("b").unwrap().bool().unwrap().get(1), Some(false));
+    }
+
+    #[test]
+    fn csv_dialects() {
+        let data =
+            b"# exported\nid;name;price;note\n1;\"a \\\"b\\\"\";1,5;NA\n2;-;2;\n3;c;4;x;extra\n";
+        let options = CsvOptions::default()
+            .with_comment_prefix("#")
+            .with_escape(b'\\')
+            .with_null_values(vec!["NA".into(), "-".into()])
+            .with_decimal_comma(true)
+            .with_truncate_ragged_lines(true);
+        let df = CsvLoader(data.to_vec(), options.clone()).load().unwrap().0;
+        assert_eq!(df.shape(), (3, 4));
+        let names: Vec<_> = df
+            .column("name")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(names, [Some("a \"b\""), None, Some("c")]);
+        assert_eq!(df.column("price").unwrap().f64().unwrap().get(0), Some(1.5));
+        assert_eq!(df.column("note").unwrap().null_count(), 2);
+
+        let options = options.with_missing_is_null(false);
+        let df = CsvLoader(data.to_vec(), options).load().unwrap().0;
+        assert_eq!(df.column("note").unwrap().str().unwrap().get(1), Some(""));
+        assert!(CsvLoader(data.to_vec(), CsvOptions::default())
+            .load()
+            .is_err());
+    }
+
+    #[test]
+    fn type_inference() {
+        let mut data = String::from("id,score\n");
+        for i in 0..20 {
+            data.push_str(&format!("{},{}\n", i, i));
+        }
+        data.push_str("20,2.5\n21,n/a\n");
+        let csv = |options: CsvOptions| CsvLoader(data.clone().into_bytes(), options).load();
+
+        let err = csv(CsvOptions::default()).unwrap_err().to_string();
+        assert!(err.contains("sample_size"), "{}", err);
+        let df = csv(CsvOptions::default().with_lenient(true)).unwrap().0;
+        assert_eq!(df.column("score").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("score").unwrap().null_count(), 2);
+
+        let full = CsvOptions::default().with_infer_schema_length(usize::MAX);
+        assert!(csv(full.clone())
+            .is_ok_and(|df| df.0.column("score").unwrap().dtype() == &DataType::String));
+        let columns = parse_columns("{score: 'DOUBLE'}").unwrap();
+        let df = csv(full.with_columns(Some(columns.clone())).with_lenient(true))
+            .unwrap()
+            .0;
+        let score = df.column("score").unwrap().f64().unwrap();
+        assert_eq!((score.get(20), score.get(21)), (Some(2.5), None));
+
+        let data = br#"[{"ts": "2024-01-31 12:00:00", "n": "1"}, {"ts": "soon", "n": "x"}]"#;
+        let columns = parse_columns("ts TIMESTAMP\nn BIGINT").unwrap();
+        let options = JsonOptions::default().with_columns(Some(columns));
+        let err = JsonLoader(data.to_vec(), options.clone())
+            .load()
+            .unwrap_err();
+        assert!(err.to_string().contains("column ts"), "{}", err);
+        let df = JsonLoader(data.to_vec(), options.with_lenient(true))
+            .load()
+            .unwrap()
+            .0;
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::Datetime(TimeUnit::Microseconds, None),
+                DataType::Int64
+            ]
+        );
+        assert_eq!(df.column("n").unwrap().null_count(), 1);
+        assert_eq!(parse_sample_size("all").unwrap(), usize::MAX);
+        assert!(parse_sample_size("0").is_err());
+    }
+
+    #[test]
+    fn content_detection() {
+        let options = QueryOptions::default();
+        let load = |location: &str, declared: Option<Format>, data: &[u8]| {
+            let loader = detect_content(location, declared, data.to_vec(), &options);
+            loader.load().unwrap().0
+        };
+
+        let df = load("", None, b"name;price\nA;1,5\nB;2\n");
+        assert_eq!(df.get_column_names(), ["name", "price"]);
+        let df = load("", None, b"a\tb|c\n1\t2|3\n");
+        assert_eq!(df.get_column_names(), ["a", "b|c"]);
+        let df = load("", None, b"a|b\n\"x|y, z\"|2\n");
+        assert_eq!(df.get_column_names(), ["a", "b"]);
+        let options = CsvOptions::default();
+        assert_eq!(sniff_delimiter(b"title\nreport\n", &options), b',');
+        let options = CsvOptions::default().with_comment_prefix("#");
+        assert_eq!(sniff_delimiter(b"# 1,2\na;b\n1;2\n", &options), b';');
+
+        // a declared type wins over the extension, magic bytes over both
+        let df = load("data.json", Some(Format::Csv), b"[1]\n");
+        assert_eq!(df.get_column_names(), ["[1]"]);
+        let df = load("data.csv.gz", None, b"[1,2]\n");
+        assert_eq!(df.get_column_names(), ["[1", "2]"]);
+        assert_eq!(
+            Format::from_extension("https://x/a.tsv?v=1"),
+            Some(Format::Csv)
+        );
+        let mut parquet = Vec::new();
+        let mut df = df!("id" => [1i64]).unwrap();
+        ParquetWriter::new(&mut parquet).finish(&mut df).unwrap();
+        assert!(load("data.csv", Some(Format::Json), &parquet).equals(&df));
+    }
+
+    #[test]
+    fn column_definitions() {
+        let expected = vec![
+            ("id".to_string(), DataType::Int64),
+            ("name".to_string(), DataType::String),
+        ];
+        assert_eq!(parse_columns("id BIGINT, name VARCHAR").unwrap(), expected);
+        assert_eq!(
+            parse_columns("{'id': 'BIGINT', name: text}").unwrap(),
+            expected
+        );
+        assert_eq!(parse_columns("{id: BIGINT, name: text}").unwrap(), expected);
+        assert_eq!(
+            parse_columns(r#"{"id": "BIGINT", "name": "VARCHAR"}"#).unwrap(),
+            expected
+        );
+        assert!(parse_columns("id").is_err());
+        assert!(parse_columns("id BLOB").is_err());
+        assert_eq!(
+            parse_columns("price DECIMAL(10, 2), code VARCHAR(3)").unwrap(),
+            [
+                ("price".to_string(), DataType::Float64),
+                ("code".to_string(), DataType::String)
+            ]
+        );
+
+        let dir = std::env::temp_dir();
+        let schema = dir.join("xq_test_schema.json");
+        std::fs::write(&schema, r#"{"b:c": "TEXT", "a,d": "DECIMAL(10,2)"}"#).unwrap();
+        let columns = read_schema(&schema.display().to_string()).unwrap();
+        assert_eq!(
+            columns,
+            [
+                ("b:c".to_string(), DataType::String),
+                ("a,d".to_string(), DataType::Float64)
+            ]
+        );
+        std::fs::write(&schema, r#"{"secret": {"nested": "TEXT"}}"#).unwrap();
+        let err = read_schema(&schema.display().to_string()).unwrap_err();
+        assert!(!err.to_string().contains("secret"), "{}", err);
+        std::fs::remove_file(schema).ok();
+
+        let schema = dir.join("xq_test_schema.txt");
+        std::fs::write(&schema, "root:x:0:0:root:/root:/bin/bash\n").unwrap();
+        let err = read_schema(&schema.display().to_string()).unwrap_err();
+        assert!(!err.to_string().contains("root"), "{}", err);
+        std::fs::remove_file(schema).ok();
+    }
+
+    #[test]
+    fn json_lines() {
+        let data = b"{\"a\": 1, \"b\": \"x\"}\n{\"a\": 2}\n\n{\"a\": 3, \"b\": \"z\"}\n".to_vec();
+        assert!(is_json_lines(&data));
+        assert!(!is_json_lines(b"{\"a\": 1}\n"));
+        assert!(!is_json_lines(b"{\n  \"a\": 1\n}\n"));
+
+        let options = QueryOptions::default();
+        let loader = detect_content("", None, data, &options);
+        assert!(matches!(loader, Loader::Ndjson(_)));
+        let df = loader.load().unwrap().0;
+        assert_eq!(df.shape(), (3, 2));
+
+        let data = b"{\"a\": 1}\n{\"a\": \n{\"a\": 3}\n".to_vec();
+        let err = NdjsonLoader(data.clone(), JsonOptions::default())
+            .load()
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        let options = JsonOptions::default().with_ignore_errors(true);
+        let df = NdjsonLoader(data.clone(), options).load().unwrap().0;
+        assert_eq!(df.column("a").unwrap().i64().unwrap().get(1), Some(3));
+
+        let options = JsonOptions::default().with_records_path(Some("a".into()));
+        assert!(NdjsonLoader(data, options).load().is_err());
+    }
+
+    #[test]
+    fn fixed_width() {
+        let data = b"report 2024\nID   NAME        AMOUNT  OK\n1    Alice, A.    10.50  true\n\n2    Bob         200\n"
+            .to_vec();
+        let options = FwfOptions::default().with_skip_rows(1);
+        let df = FwfLoader(data.clone(), options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["ID", "NAME", "AMOUNT", "OK"]);
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::Int64,
+                DataType::String,
+                DataType::Float64,
+                DataType::Boolean
+            ]
+        );
+        assert_eq!(
+            df.column("NAME").unwrap().str().unwrap().get(0),
+            Some("Alice, A.")
+        );
+        assert_eq!(df.column("OK").unwrap().bool().unwrap().get(1), None);
+
+        let columns = parse_fwf_columns("id:1:2, name : 6 : 5").unwrap();
+        assert_eq!(columns[1], ("name".to_string(), 6, 5));
+        let options = FwfOptions::default()
+            .with_columns(Some(columns))
+            .with_skip_rows(2);
+        let df = FwfLoader(data, options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["id", "name"]);
+        assert_eq!(
+            df.column("name").unwrap().str().unwrap().get(0),
+            Some("Alice")
+        );
+        assert!(parse_fwf_columns("id:0:2").is_err());
+        assert!(parse_fwf_columns("id:1").is_err());
+        let options = FwfOptions::default().with_columns(Some(vec![("id".into(), 0, 2)]));
+        assert!(FwfLoader(b"1\n".to_vec(), options).load().is_err());
+    }
+
+    #[test]
+    fn yaml_and_toml() {
+        let yaml = b"- {id: 1, user: {name: a}}\n- {id: 2, user: {name: b}}\n".to_vec();
+        let df = YamlLoader(yaml, JsonOptions::default()).load().unwrap().0;
+        assert_eq!(df.shape(), (2, 2));
+
+        let yaml = b"---\nid: 1\n---\nid: 2\nextra: x\n---\n".to_vec();
+        let loader = detect_content("", None, yaml, &QueryOptions::default());
+        assert!(matches!(loader, Loader::Yaml(_)));
+        assert_eq!(loader.load().unwrap().0.shape(), (2, 2));
+
+        let lock = b"version = 3\n\n[[package]]\nname = \"a\"\n\n[[package]]\nname = \"b\"\n";
+        let df = TomlLoader(lock.to_vec(), JsonOptions::default())
+            .load()
+            .unwrap()
+            .0;
+        assert_eq!(df.get_column_names(), ["name"]);
+        assert_eq!(df.height(), 2);
+
+        // an empty array holds no tables
+        let doc = b"tags = []
+
+[[item]]
+id = 1
+";
+        let df = TomlLoader(doc.to_vec(), JsonOptions::default())
+            .load()
+            .unwrap()
+            .0;
+        assert_eq!(df.get_column_names(), ["id"]);
+
+        let manifest = b"[package]\nname = \"xq\"\nreleased = 2024-01-02T03:04:05Z\n";
+        let options = JsonOptions::default().with_flatten(true);
+        let df = TomlLoader(manifest.to_vec(), options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["package.name", "package.released"]);
+    }
+
+    #[test]
+    fn nested_json() {
+        let data = br#"{"data": {"items": [
+            {"id": 1, "user": {"name": "a", "address": {"city": "x"}}},
+            {"id": 2, "user": {"name": "b", "address": {"city": "y"}}}
+        ]}}"#
+            .to_vec();
+        let value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(
+            select(&value, "$.data['items'][1].id"),
+            Some(&serde_json::json!(2))
+        );
+
+        let options = JsonOptions::default()
+            .with_records_path(Some("$.data.items".into()))
+            .with_flatten(true);
+        let df = JsonLoader(data, options).load().unwrap().0;
+        assert_eq!(
+            df.get_column_names(),
+            ["id", "user.name", "user.address.city"]
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use polars::prelude::*;
+use regex::Regex;
+
+use crate::loader::Load;
+use crate::DataSet;
+
+/// Lines of a log, parsed into a row each.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogFormat {
+    /// Apache/Nginx common or combined access log.
+    Access,
+    /// RFC 5424 or RFC 3164 syslog.
+    Syslog,
+    /// `key=value` pairs, values may be double quoted.
+    Logfmt,
+    /// A regular expression, its named groups are the columns.
+    Regex(String),
+}
+
+#[derive(Debug)]
+pub struct LogLoader(pub(crate) Vec<u8>, pub(crate) LogFormat);
+
+const ACCESS: &str = r#"^(?P<host>\S+) (?P<ident>\S+) (?P<user>\S+) \[(?P<time>[^\]]+)\] "(?:(?P<method>[A-Z]+) (?P<path>\S+)(?: (?P<protocol>[^"]*))?|[^"]*)" (?P<status>\d{3}) (?P<size>\d+|-)(?: "(?P<referer>[^"]*)" "(?P<user_agent>[^"]*)")?"#;
+
+const SYSLOG_5424: &str = r"^<(?P<pri>\d{1,3})>(?P<version>\d{1,2}) (?P<timestamp>\S+) (?P<hostname>\S+) (?P<app_name>\S+) (?P<procid>\S+) (?P<msgid>\S+) (?P<structured_data>-|(?:\[(?:[^\]\\]|\\.)*\])+)(?: (?P<message>.*))?$";
+
+const SYSLOG_3164: &str = r"^(?:<(?P<pri>\d{1,3})>)?(?P<timestamp>[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}) (?P<hostname>\S+) (?P<app_name>[^:\[\s]+)(?:\[(?P<procid>[^\]]+)\])?: ?(?P<message>.*)$";
+
+const SYSLOG_COLUMNS: [&str; 9] = [
+    "facility",
+    "severity",
+    "timestamp",
+    "hostname",
+    "app_name",
+    "procid",
+    "msgid",
+    "structured_data",
+    "message",
+];
+
+/// Values parsed from the lines, a column each.
+struct Columns {
+    names: Vec<String>,
+    values: Vec<Vec<Option<String>>>,
+    rows: usize,
+    skipped: Vec<usize>,
+}
+
+impl Columns {
+    fn new(names: Vec<String>) -> Self {
+        let values = vec![Vec::new(); names.len()];
+        Self {
+            names,
+            values,
+            rows: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, row: impl IntoIterator<Item = Option<String>>) {
+        for (column, value) in self.values.iter_mut().zip(row) {
+            column.push(value);
+        }
+        self.rows += 1;
+    }
+
+    /// The value of `name` in the current row, adding the column if needed.
+    fn set(&mut self, name: &str, value: String) {
+        let index = match self.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.values.push(vec![None; self.rows + 1]);
+                self.names.len() - 1
+            }
+        };
+        self.values[index][self.rows] = Some(value);
+    }
+
+    fn start_row(&mut self) {
+        for column in &mut self.values {
+            column.push(None);
+        }
+    }
+
+    fn into_frame(self) -> Result<DataFrame> {
+        if self.rows == 0 && !self.skipped.is_empty() {
+            return Err(anyhow!("No line matches the log format"));
+        }
+        if !self.skipped.is_empty() {
+            let shown: Vec<_> = self
+                .skipped
+                .iter()
+                .take(10)
+                .map(|i| i.to_string())
+                .collect();
+            tracing::warn!(
+                "skipped {} lines not matching the log format: {}{}",
+                self.skipped.len(),
+                shown.join(", "),
+                if self.skipped.len() > shown.len() {
+                    ", ..."
+                } else {
+                    ""
+                }
+            );
+        }
+        let series = self
+            .names
+            .iter()
+            .zip(self.values)
+            .map(|(name, values)| infer(name, values))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DataFrame::new(series)?)
+    }
+}
+
+impl Load for LogLoader {
+    type Error = anyhow::Error;
+
+    /// Lines that do not match the format, such as continuations of a
+    /// stack trace, are skipped with a warning.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let LogLoader(data, format) = self;
+        let text = String::from_utf8_lossy(&data);
+        let lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim_end_matches('\r')))
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let columns = match format {
+            LogFormat::Access => {
+                let regex = Regex::new(ACCESS)?;
+                let mut columns = match_lines(&regex, lines)?;
+                if let Some(i) = columns.names.iter().position(|n| n == "time") {
+                    for value in columns.values[i].iter_mut() {
+                        *value = value.as_deref().and_then(access_time);
+                    }
+                }
+                columns
+            }
+            LogFormat::Syslog => syslog(lines)?,
+            LogFormat::Logfmt => {
+                let mut columns = Columns::new(Vec::new());
+                for (_, line) in lines {
+                    columns.start_row();
+                    for (key, value) in logfmt(line) {
+                        columns.set(&key, value);
+                    }
+                    columns.rows += 1;
+                }
+                columns
+            }
+            LogFormat::Regex(pattern) => {
+                let regex = Regex::new(&pattern)
+                    .map_err(|e| anyhow!("Invalid pattern {}: {}", pattern, e))?;
+                match_lines(&regex, lines)?
+            }
+        };
+        Ok(DataSet(columns.into_frame()?))
+    }
+}
+
+/// A row per line matching `regex`, a column per named group. `-` stands
+/// for a missing value, as in access logs.
+fn match_lines<'a>(
+    regex: &Regex,
+    lines: impl Iterator<Item = (usize, &'a str)>,
+) -> Result<Columns> {
+    let names: Vec<_> = regex.capture_names().flatten().collect();
+    if names.is_empty() {
+        return Err(anyhow!("Pattern {} has no named groups", regex));
+    }
+    let mut columns = Columns::new(names.iter().map(|n| n.to_string()).collect());
+    for (i, line) in lines {
+        match regex.captures(line) {
+            Some(captures) => columns.push(names.iter().map(|name| {
+                captures
+                    .name(name)
+                    .map(|m| m.as_str())
+                    .filter(|v| *v != "-")
+                    .map(String::from)
+            })),
+            None => columns.skipped.push(i),
+        }
+    }
+    Ok(columns)
+}
+
+fn syslog<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Columns> {
+    let rfc5424 = Regex::new(SYSLOG_5424)?;
+    let rfc3164 = Regex::new(SYSLOG_3164)?;
+    let now = Utc::now().naive_utc();
+
+    let mut columns = Columns::new(SYSLOG_COLUMNS.iter().map(|c| c.to_string()).collect());
+    for (i, line) in lines {
+        let (captures, dated) = if let Some(captures) = rfc5424.captures(line) {
+            (captures, true)
+        } else if let Some(captures) = rfc3164.captures(line) {
+            (captures, false)
+        } else {
+            columns.skipped.push(i);
+            continue;
+        };
+
+        let get = |name: &str| {
+            captures
+                .name(name)
+                .map(|m| m.as_str())
+                .filter(|v| *v != "-" && !v.is_empty())
+                .map(String::from)
+        };
+        let timestamp = match dated {
+            true => get("timestamp"),
+            false => get("timestamp").and_then(|t| rfc3164_time(&t, now)),
+        };
+        let pri = get("pri").and_then(|p| p.parse::<u8>().ok());
+        columns.push([
+            pri.map(|p| (p / 8).to_string()),
+            pri.map(|p| (p % 8).to_string()),
+            timestamp,
+            get("hostname"),
+            get("app_name"),
+            get("procid"),
+            get("msgid"),
+            get("structured_data"),
+            get("message"),
+        ]);
+    }
+    Ok(columns)
+}
+
+/// RFC 3164 times such as `Feb  5 17:32:18`, as RFC 3339. They have no
+/// year: the latest one not putting them after `now` is taken.
+fn rfc3164_time(value: &str, now: NaiveDateTime) -> Option<String> {
+    let value = value.replace("  ", " ");
+    let parse = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, value), "%Y %b %d %H:%M:%S").ok()
+    };
+    parse(now.year())
+        .filter(|t| *t <= now)
+        .or_else(|| parse(now.year() - 1))
+        .map(|t| t.and_utc().to_rfc3339())
+}
+
+/// Access log times such as `10/Oct/2000:13:55:36 -0700`, as RFC 3339.
+fn access_time(value: &str) -> Option<String> {
+    DateTime::parse_from_str(value, "%d/%b/%Y:%H:%M:%S %z")
+        .ok()
+        .map(|t| t.with_timezone(&Utc).to_rfc3339())
+}
+
+/// The `key=value` pairs of a logfmt line, a bare key being `true`.
+fn logfmt(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let key: String =
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=')).collect();
+        if key.is_empty() {
+            match chars.next() {
+                Some(_) => continue,
+                None => break,
+            }
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            pairs.push((key, "true".to_string()));
+            continue;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())));
+        }
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// A column of the narrowest type holding its values: integer, float,
+/// boolean, RFC 3339 datetime or string.
+pub(crate) fn infer(name: &str, values: Vec<Option<String>>) -> Result<Series> {
+    fn all<T>(
+        values: &[Option<String>],
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Option<Vec<Option<T>>> {
+        values
+            .iter()
+            .map(|v| match v {
+                Some(v) => parse(v).map(Some),
+                None => Some(None),
+            })
+            .collect()
+    }
+
+    if values.iter().all(Option::is_none) {
+        return Ok(Series::new(name, values));
+    }
+    if let Some(ints) = all(&values, |v| v.parse::<i64>().ok()) {
+        return Ok(Series::new(name, ints));
+    }
+    if let Some(floats) = all(&values, |v| v.parse::<f64>().ok()) {
+        return Ok(Series::new(name, floats));
+    }
+    if let Some(bools) = all(&values, |v| v.parse::<bool>().ok()) {
+        return Ok(Series::new(name, bools));
+    }
+    if let Some(millis) = all(&values, |v| {
+        DateTime::parse_from_rfc3339(v)
+            .ok()
+            .map(|t| t.timestamp_millis())
+    }) {
+        let series = Series::new(name, millis);
+        return Ok(series.cast(&DataType::Datetime(
+            TimeUnit::Milliseconds,
+            Some("UTC".into()),
+        ))?);
+    }
+    Ok(Series::new(name, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(data: &str, format: LogFormat) -> DataFrame {
+        LogLoader(data.as_bytes().to_vec(), format)
+            .load()
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn access_log() {
+        let df = load(
+            concat!(
+                "127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /a.gif HTTP/1.0\" 200 2326 \"http://x/\" \"curl/8\"\n",
+                "10.0.0.2 - - [10/Oct/2000:13:56:01 -0700] \"POST /login HTTP/1.1\" 302 -\n",
+                "garbage\n",
+            ),
+            LogFormat::Access,
+        );
+        assert_eq!(df.height(), 2);
+        assert_eq!(df.column("status").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("size").unwrap().null_count(), 1);
+        assert_eq!(
+            df.column("user_agent").unwrap().str().unwrap().get(0),
+            Some("curl/8")
+        );
+        assert!(matches!(
+            df.column("time").unwrap().dtype(),
+            DataType::Datetime(_, _)
+        ));
+    }
+
+    #[test]
+    fn syslog_formats() {
+        let df = load(
+            concat!(
+                "<34>1 2003-10-11T22:14:15.003Z mymachine su - ID47 - 'su root' failed\n",
+                "<13>Feb  5 17:32:18 host sshd[4123]: Accepted publickey\n",
+            ),
+            LogFormat::Syslog,
+        );
+        assert_eq!(df.get_column_names(), SYSLOG_COLUMNS);
+        let facility: Vec<_> = df
+            .column("facility")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(facility, [Some(4), Some(1)]);
+        assert_eq!(
+            df.column("procid").unwrap().i64().unwrap().get(1),
+            Some(4123)
+        );
+        assert_eq!(df.column("timestamp").unwrap().null_count(), 0);
+
+        // a nil RFC 5424 timestamp is null
+        let df = load("<34>1 - mymachine su - ID47 - failed\n", LogFormat::Syslog);
+        assert_eq!(df.column("timestamp").unwrap().null_count(), 1);
+
+        let now =
+            NaiveDateTime::parse_from_str("2025-01-02 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            rfc3164_time("Jan  1 23:59:59", now).as_deref(),
+            Some("2025-01-01T23:59:59+00:00")
+        );
+        assert_eq!(
+            rfc3164_time("Dec 31 23:59:59", now).as_deref(),
+            Some("2024-12-31T23:59:59+00:00")
+        );
+    }
+
+    #[test]
+    fn logfmt_and_regex() {
+        let df = load(
+            "level=info msg=\"user logged in\" ms=12 ok\nlevel=error ms=40.5 err=\"a \\\"b\\\"\"\n",
+            LogFormat::Logfmt,
+        );
+        assert_eq!(df.get_column_names(), ["level", "msg", "ms", "ok", "err"]);
+        assert_eq!(df.column("ms").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(
+            df.column("err").unwrap().str().unwrap().get(1),
+            Some("a \"b\"")
+        );
+
+        let pattern = r"^(?P<level>\w+): (?P<code>\d+)".to_string();
+        let df = load("WARN: 12\nnope\nERROR: 500\n", LogFormat::Regex(pattern));
+        assert_eq!(df.shape(), (2, 2));
+        assert!(LogLoader(b"x".to_vec(), LogFormat::Regex("(a)".into()))
+            .load()
+            .is_err());
+    }
+}
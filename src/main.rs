@@ -1,24 +1,123 @@
 use anyhow::Result;
 use std::env;
-use xq::{query, DataSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing_subscriber::EnvFilter;
+use xq::{
+    default_config_path, parse_char, parse_columns, parse_header, parse_sample_size,
+    query_with_options, read_schema, Auth, Cache, CsvOptions, DataSet, HttpOptions, JsonOptions,
+    QueryOptions,
+};
 
 fn print_help() {
     println!("XQ - SQL Query Tool for Multiple Data Sources");
     println!("\nUsage:");
     println!("  xq <SQL_QUERY> [OPTIONS]");
+    println!("  xq cache ls | clear [--cache-dir <PATH>] [--config <PATH>]");
     println!("  xq --help | -h");
     println!("\nOptions:");
     println!("  --format <FORMAT>    Output format: table (default), json, csv");
+    println!(
+        "  --input-format <FMT> Input format: csv, json, ndjson, parquet, excel, arrow, avro,"
+    );
+    println!("                       xml, html, yaml, toml, fwf (detected by default),");
+    println!("                       or access_log, syslog, logfmt for logs");
+    println!("  --ignore-errors      Skip malformed lines of JSON Lines data");
+    println!("  --flatten            Flatten nested JSON objects into a.b.c columns");
+    println!("  --filename           Add a _filename column with the file each row came from");
+    println!("  --encoding <NAME>    Text encoding, e.g. gbk, gb18030, big5, utf-16le (default");
+    println!("                       told by a byte order mark or HTTP charset, else UTF-8)");
+    println!("\nType Options (CSV and JSON):");
+    println!("  --columns <SPEC>        Column types, e.g. 'id BIGINT, ts TIMESTAMP'");
+    println!("  --schema <PATH>         File of column types, e.g. {{\"id\": \"BIGINT\"}}");
+    println!("  --sample-size <N|all>   Rows the types are inferred from (CSV 16, JSON 100)");
+    println!("  --lenient               Read values that do not parse as null, warning how many");
+    println!("\nCSV Options (detected from the data when not given):");
+    println!("  --delimiter <CHAR>      Field delimiter, e.g. ';' or '\\t'");
+    println!("  --quote <CHAR>          Quote character (default \")");
+    println!("  --escape <CHAR>         Escape character inside quotes, e.g. '\\'");
+    println!("  --comment <PREFIX>      Skip lines starting with PREFIX");
+    println!("  --no-header             The first line is data, columns are column_1, column_2...");
+    println!("  --skip-rows <N>         Lines skipped before the header");
+    println!("  --null <TOKEN>          Read TOKEN as null, may be repeated, e.g. NA or -");
+    println!("  --missing-is-null <B>   Whether empty fields are null (default true)");
+    println!("  --decimal-comma         Read floats written as 1,5");
+    println!("  --truncate-ragged-lines Drop extra fields of lines longer than the header");
+    println!("\nHTTP Options (values may reference environment variables as ${{NAME}}):");
+    println!("  --header <NAME: VALUE>  Add a request header, may be repeated");
+    println!("  --bearer <TOKEN>        Send an `Authorization: Bearer` token");
+    println!("  --basic <USER[:PASS]>   Use basic authentication");
+    println!("  --method <METHOD>       Request method (default GET)");
+    println!("  --body <BODY>           Request body");
+    println!("  --user-agent <AGENT>    User-Agent header");
+    println!("  --connect-timeout <SECS>  Time allowed to connect");
+    println!("  --read-timeout <SECS>   Time allowed waiting for response data");
+    println!("  --retries <N>           Retries for 429/5xx responses (default 3, none for POST)");
+    println!("  --retry-backoff <SECS>  Delay before the first retry (default 0.5)");
+    println!("  --max-response-size <BYTES>  Largest response accepted");
+    println!("  --paginate <SCHEME>     Request every page of a JSON API: link,");
+    println!(
+        "                          cursor:<param>:<path>, page:<param>[:<start>], offset:<param>"
+    );
+    println!("  --records-path <PATH>   Path of the records in the JSON (each page when");
+    println!("                          paginating), e.g. data.items or $.data['items']");
+    println!("  --max-pages <N>         Most pages requested (default 100)");
+    println!("  --config <PATH>         Config file with per-host settings");
+    println!("                          (default $XQ_CONFIG or ~/.config/xq/config.toml)");
+    println!("\nCache Options (also enabled by a [cache] section of the config file):");
+    println!("  --cache                 Cache HTTP responses in the cache directory");
+    println!("  --no-cache              Neither read nor write the cache");
+    println!("  --refresh               Download again and update the cache");
+    println!("  --cache-ttl <SECS>      Use cached responses without revalidation this long (default 3600)");
+    println!("  --cache-dir <PATH>      Cache directory (default $XQ_CACHE_DIR or ~/.cache/xq)");
+    println!("  --cache-authenticated   Also cache requests with credentials");
     println!("  --help, -h           Show this help message");
+    println!("\nThe format of a source is told by its magic bytes, Content-Type, extension or");
+    println!("content, CSV delimiters (, ; tab |) are detected; RUST_LOG=xq=debug shows which.");
     println!("\nExamples:");
     println!("  xq \"SELECT * FROM https://example.com/data.csv WHERE value > 100\"");
     println!("  xq \"SELECT name, COUNT(*) FROM file:///path/to/data.csv GROUP BY name\"");
     println!("  xq \"SELECT * FROM file:///data.csv\" --format json");
+    println!("  xq \"SELECT * FROM 'file:///logs/2024-*.csv'\" --filename");
+    println!("  curl -s https://example.com/data.json | xq \"SELECT * FROM stdin\"");
+    println!("  xq \"SELECT * FROM https://api.example.com/items\" --bearer '${{API_TOKEN}}'");
+    println!("  xq \"SELECT * FROM file:///data/exports/ WHERE year = 2024\"");
+    println!("  xq \"SELECT * FROM file:///data/report.xlsx#Sheet2\"");
+    println!("  xq \"SELECT * FROM https://example.com/stats.html#sales\"");
+    println!("  xq \"SELECT * FROM sqlite:///data/app.db?table=events\"");
+    println!("  xq \"SELECT name FROM postgres://me@localhost/app/users WHERE age > 20\"");
     println!("\nSupported Data Sources:");
-    println!("  - HTTP/HTTPS URLs (CSV/JSON format)");
-    println!("  - Local files with file:// protocol (CSV/JSON format)");
+    println!("  - HTTP/HTTPS URLs (CSV/JSON/JSON Lines/Parquet/Excel/Arrow IPC/Avro/XML/HTML/YAML/TOML format, Parquet read with range requests)");
+    println!("  - Local files with file:// protocol (CSV/JSON/JSON Lines/Parquet/Excel/Arrow IPC/Avro/XML/HTML/YAML/TOML format)");
+    println!("  - Standard input with -, stdin or stdin://");
+    println!("  - Reader options: read_csv(source, delim => '|', header => false, skip => 1,");
+    println!("    quote => '\"', escape => '\\', comment => '#', nullstr => 'NA',");
+    println!("    nullstrs => 'NA,-', missing_is_null => true, decimal_comma => true,");
+    println!("    truncate_ragged_lines => true, columns => {{id: 'BIGINT', name: 'VARCHAR'}},");
+    println!("    schema => 'schema.json',");
+    println!("    sample_size => 1000 or 'all', lenient => true),");
+    println!("    read_json(source, records_path => 'data.items', flatten => true, columns, ...),");
+    println!("    read_parquet(source), read_excel(source, sheet => 'Q3', range => 'B3:F100', header => true)");
+    println!(
+        "    read_ndjson(source, ignore_errors => true), read_yaml(source), read_toml(source)"
+    );
+    println!("    read_fwf(source, columns => 'id:1:5, name:6:20', header => false, skip => 0)");
+    println!("    text readers also take encoding => 'gbk'");
+    println!(
+        "    read_xml(source, path => 'catalog/book'), read_html(source, table => 'sales' or 0)"
+    );
+    println!("  - Logs: --input-format access_log|syslog|logfmt, or read_regex(source, pattern)");
+    println!("    with named groups (?P<name>...) as columns");
+    println!("  - Inline data: 'data:text/csv,a,b%0A1,2' or (VALUES (1, 'a')) AS t(id, name)");
+    println!("  - Glob patterns and directories, with hive-style partitions (key=value/)");
+    println!(
+        "  - PostgreSQL/MySQL tables: postgres://user@host/db/table, mysql://user@host/db/table"
+    );
+    println!("  - SQLite tables or queries: sqlite:///app.db?table=t or sqlite('app.db', query => '...')");
     println!("\nSupported SQL Features:");
     println!("  - SELECT with column selection or *");
+    println!("  - UNNEST(list_column) for a row per list element");
+    println!("  - Struct fields user.address.city, user['name'], list items tags[0]");
+    println!("  - JSON strings: payload -> 'user' ->> 'name', json_extract(payload, '$.a.b')");
     println!("  - WHERE conditions");
     println!("  - GROUP BY");
     println!("  - Aggregation functions: SUM, COUNT, MAX, MIN, AVG");
@@ -26,6 +125,103 @@ fn print_help() {
     println!("  - LIMIT and OFFSET");
 }
 
+/// The value following `option`, exiting when it is missing.
+fn value<'a>(rest: &mut impl Iterator<Item = &'a String>, option: &str) -> &'a str {
+    match rest.next() {
+        Some(v) => v,
+        None => {
+            eprintln!("Error: {} requires a value", option);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(v: &str) -> Result<T> {
+    v.parse()
+        .map_err(|_| anyhow::anyhow!("{} is not a valid number", v))
+}
+
+fn parse_bool(v: &str) -> Result<bool> {
+    v.parse()
+        .map_err(|_| anyhow::anyhow!("{} is not true or false", v))
+}
+
+fn parse_seconds(v: &str) -> Result<Duration> {
+    Duration::try_from_secs_f64(parse_number(v)?)
+        .map_err(|_| anyhow::anyhow!("{} is not a valid number of seconds", v))
+}
+
+fn exit_on_error<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Default options overlaid with the config file, `path` or the default one.
+fn load_config(path: Option<String>) -> QueryOptions {
+    let options = QueryOptions::default();
+    match (path, default_config_path()) {
+        (Some(path), _) => exit_on_error(options.with_config_file(path)),
+        (None, Some(path)) if path.exists() => exit_on_error(options.with_config_file(path)),
+        _ => options,
+    }
+}
+
+fn cache_command(args: &[String]) {
+    let mut config = None;
+    let mut dir = None;
+    let mut rest = args[1..].iter();
+    while let Some(option) = rest.next() {
+        match option.as_str() {
+            "--config" => config = Some(value(&mut rest, option).to_string()),
+            "--cache-dir" => dir = Some(value(&mut rest, option).to_string()),
+            unknown => {
+                eprintln!("Error: Unknown option {}", unknown);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let cache = match (dir, load_config(config).cache) {
+        (Some(dir), _) => Cache::new(dir),
+        (None, Some(cache)) => cache,
+        (None, None) => match Cache::default_dir() {
+            Some(dir) => Cache::new(dir),
+            None => {
+                eprintln!("Error: No cache directory, use --cache-dir");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    match args.first().map(String::as_str) {
+        Some("ls") => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            println!("{:>12}  {:>10}  URL", "SIZE", "AGE");
+            for entry in exit_on_error(cache.entries()) {
+                let age = now.saturating_sub(entry.fetched_at);
+                println!("{:>12}  {:>9}s  {}", entry.size, age, entry.url);
+            }
+        }
+        Some("clear") => {
+            let count = exit_on_error(cache.clear());
+            println!(
+                "Removed {} cached responses from {}",
+                count,
+                cache.dir().display()
+            );
+        }
+        _ => {
+            eprintln!("Error: Expected `xq cache ls` or `xq cache clear`");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn format_dataframe(df: &DataSet, format: &str) -> String {
     match format {
         "json" => df
@@ -43,6 +239,13 @@ fn format_dataframe(df: &DataSet, format: &str) -> String {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // warnings by default, RUST_LOG=xq=debug also tells how sources are read
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
@@ -58,16 +261,180 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if arg == "cache" {
+        cache_command(&args[2..]);
+        return Ok(());
+    }
+
     let sql = arg;
 
-    // Parse format option
-    let format = if args.len() > 2 && args[2] == "--format" && args.len() > 3 {
-        &args[3]
-    } else {
-        "table"
+    let mut format = "table";
+    let mut format_option = None;
+    let mut ignore_errors = false;
+    let mut flatten = false;
+    let mut filename_column = false;
+    let mut encoding = None;
+    let mut csv = CsvOptions::default();
+    let mut columns: Option<Vec<_>> = None;
+    let mut sample_size = None;
+    let mut lenient = false;
+    let mut http = HttpOptions::default();
+    let mut config = None;
+    let mut use_cache = false;
+    let mut no_cache = false;
+    let mut cache_authenticated = false;
+    let mut refresh = false;
+    let mut cache_ttl = None;
+    let mut cache_dir = None;
+    let mut rest = args[2..].iter();
+    while let Some(option) = rest.next() {
+        match option.as_str() {
+            "--format" => format = value(&mut rest, option),
+            "--input-format" => {
+                format_option = Some(exit_on_error(value(&mut rest, option).parse()));
+            }
+            "--ignore-errors" => ignore_errors = true,
+            "--flatten" => flatten = true,
+            "--filename" => filename_column = true,
+            "--encoding" => encoding = Some(value(&mut rest, option).to_string()),
+            "--delimiter" => {
+                let delimiter = exit_on_error(parse_char(value(&mut rest, option), option));
+                csv = csv.with_delimiter(delimiter);
+            }
+            "--quote" => {
+                let quote = exit_on_error(parse_char(value(&mut rest, option), option));
+                csv = csv.with_quote(quote);
+            }
+            "--escape" => {
+                let escape = exit_on_error(parse_char(value(&mut rest, option), option));
+                csv = csv.with_escape(escape);
+            }
+            "--comment" => csv = csv.with_comment_prefix(value(&mut rest, option)),
+            "--no-header" => csv = csv.with_header(false),
+            "--skip-rows" => {
+                csv = csv.with_skip_rows(exit_on_error(parse_number(value(&mut rest, option))));
+            }
+            "--null" => csv.null_values.push(value(&mut rest, option).to_string()),
+            "--missing-is-null" => {
+                csv = csv.with_missing_is_null(exit_on_error(parse_bool(value(&mut rest, option))));
+            }
+            "--decimal-comma" => csv = csv.with_decimal_comma(true),
+            "--truncate-ragged-lines" => csv = csv.with_truncate_ragged_lines(true),
+            "--columns" => {
+                let spec = exit_on_error(parse_columns(value(&mut rest, option)));
+                columns.get_or_insert_with(Vec::new).extend(spec);
+            }
+            "--schema" => {
+                let schema = exit_on_error(read_schema(value(&mut rest, option)));
+                columns.get_or_insert_with(Vec::new).extend(schema);
+            }
+            "--sample-size" => {
+                sample_size = Some(exit_on_error(parse_sample_size(value(&mut rest, option))));
+            }
+            "--lenient" => lenient = true,
+            "--header" => {
+                let (name, v) = exit_on_error(parse_header(value(&mut rest, option)));
+                http = http.with_header(name, v);
+            }
+            "--bearer" => http = http.with_auth(Auth::Bearer(value(&mut rest, option).into())),
+            "--basic" => {
+                let credentials = value(&mut rest, option);
+                let (username, password) = match credentials.split_once(':') {
+                    Some((u, p)) => (u.to_string(), Some(p.to_string())),
+                    None => (credentials.to_string(), None),
+                };
+                http = http.with_auth(Auth::Basic { username, password });
+            }
+            "--method" => http = http.with_method(value(&mut rest, option)),
+            "--body" => http = http.with_body(value(&mut rest, option)),
+            "--user-agent" => http = http.with_user_agent(value(&mut rest, option)),
+            "--connect-timeout" => {
+                let secs = exit_on_error(parse_seconds(value(&mut rest, option)));
+                http = http.with_connect_timeout(secs);
+            }
+            "--read-timeout" => {
+                let secs = exit_on_error(parse_seconds(value(&mut rest, option)));
+                http = http.with_read_timeout(secs);
+            }
+            "--retries" => {
+                http = http.with_retries(exit_on_error(parse_number(value(&mut rest, option))));
+            }
+            "--retry-backoff" => {
+                let secs = exit_on_error(parse_seconds(value(&mut rest, option)));
+                http = http.with_retry_backoff(secs);
+            }
+            "--max-response-size" => {
+                let size = exit_on_error(parse_number(value(&mut rest, option)));
+                http = http.with_max_response_size(size);
+            }
+            "--paginate" => {
+                http = http.with_pagination(exit_on_error(value(&mut rest, option).parse()));
+            }
+            "--records-path" => http = http.with_records_path(value(&mut rest, option)),
+            "--max-pages" => {
+                http = http.with_max_pages(exit_on_error(parse_number(value(&mut rest, option))));
+            }
+            "--config" => config = Some(value(&mut rest, option).to_string()),
+            "--cache" => use_cache = true,
+            "--no-cache" => no_cache = true,
+            "--cache-authenticated" => cache_authenticated = true,
+            "--refresh" => refresh = true,
+            "--cache-ttl" => {
+                cache_ttl = Some(exit_on_error(parse_seconds(value(&mut rest, option))))
+            }
+            "--cache-dir" => cache_dir = Some(value(&mut rest, option).to_string()),
+            unknown => {
+                eprintln!("Error: Unknown option {}", unknown);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // pages are merged into a list of records, otherwise the records are
+    // picked from the document when it is loaded
+    let records_path = match http.pagination {
+        None => http.records_path.clone(),
+        Some(_) => None,
+    };
+
+    let mut json = JsonOptions::default()
+        .with_records_path(records_path)
+        .with_flatten(flatten)
+        .with_ignore_errors(ignore_errors)
+        .with_columns(columns.clone())
+        .with_lenient(lenient);
+    csv = csv.with_columns(columns).with_lenient(lenient);
+    if let Some(sample_size) = sample_size {
+        csv = csv.with_infer_schema_length(sample_size);
+        json = json.with_infer_schema_length(sample_size);
+    }
+
+    // command line options take precedence over the config file
+    let mut options = load_config(config)
+        .with_format(format_option)
+        .with_filename_column(filename_column)
+        .with_encoding(encoding)
+        .with_csv(csv)
+        .with_json(json)
+        .with_http(http);
+    let cache = match (options.cache.take(), cache_dir) {
+        _ if no_cache => None,
+        (Some(cache), Some(dir)) => Some(cache.with_dir(dir)),
+        (None, Some(dir)) => Some(Cache::new(dir)),
+        (None, None) if use_cache => Cache::default_dir().map(Cache::new),
+        (cache, None) => cache,
     };
+    let cache = cache.map(|cache| match cache_ttl {
+        Some(ttl) => cache.with_ttl(ttl).with_refresh(refresh),
+        None => cache.with_refresh(refresh),
+    });
+    let cache = cache.map(|cache| match cache_authenticated {
+        true => cache.with_authenticated(true),
+        false => cache,
+    });
+    options = options.with_cache(cache);
 
-    match query(sql).await {
+    match query_with_options(sql, &options).await {
         Ok(df) => {
             println!("{}", format_dataframe(&df, format));
         }
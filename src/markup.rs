@@ -0,0 +1,448 @@
+use anyhow::{anyhow, Result};
+use polars::prelude::*;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use scraper::{ElementRef, Html, Selector};
+
+use crate::convert::TableFunction;
+use crate::loader::Load;
+use crate::logs::infer;
+use crate::DataSet;
+
+/// How XML is read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmlOptions {
+    /// Path of the repeated element read as rows, e.g. `catalog/book`, the
+    /// children of the root element when unset. A leading `/` anchors the
+    /// path at the root, otherwise it matches at any depth.
+    pub row_path: Option<String>,
+}
+
+/// How HTML is read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HtmlOptions {
+    /// Table read, by its zero based index, e.g. `1`, or by its id, e.g.
+    /// `sales`, a number past the last table being matched as an id. The
+    /// first table when unset.
+    pub table: Option<String>,
+}
+
+#[derive(Default, Debug)]
+pub struct XmlLoader(pub(crate) Vec<u8>, pub(crate) XmlOptions);
+
+#[derive(Default, Debug)]
+pub struct HtmlLoader(pub(crate) Vec<u8>, pub(crate) HtmlOptions);
+
+impl XmlOptions {
+    pub fn with_row_path(mut self, row_path: Option<String>) -> Self {
+        self.row_path = row_path;
+        self
+    }
+
+    /// The options of `read_xml(source, path => 'catalog/book')`.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(1, &["source", "path"])?;
+        Ok(XmlOptions {
+            row_path: func.string(usize::MAX, "path")?,
+        })
+    }
+}
+
+impl HtmlOptions {
+    pub fn with_table(mut self, table: Option<String>) -> Self {
+        self.table = table;
+        self
+    }
+
+    /// The options of `read_html(source, table => 'sales')`, or
+    /// `table => 1` for the second table.
+    pub(crate) fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(1, &["source", "table"])?;
+        let table = match func.integer("table") {
+            Ok(index) => index.map(|i| i.to_string()),
+            Err(_) => func.string(usize::MAX, "table")?,
+        };
+        Ok(HtmlOptions { table })
+    }
+}
+
+impl Load for XmlLoader {
+    type Error = anyhow::Error;
+
+    /// A row per element at the row path. Its attributes and the text of its
+    /// descendants are the columns, named by their path below the row, e.g.
+    /// `id`, `title` or `author.name`. The values of an element repeated in a
+    /// row are joined with `, `.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let XmlLoader(data, options) = self;
+        let path = options.row_path.as_deref().map(|path| {
+            let anchored = path.starts_with('/');
+            let names: Vec<String> = path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect();
+            (anchored, names)
+        });
+        let is_row = |stack: &[String]| match &path {
+            Some((true, names)) => stack == names.as_slice(),
+            Some((false, names)) => stack.ends_with(names),
+            None => stack.len() == 2,
+        };
+
+        let mut reader = Reader::from_reader(data.as_slice());
+        let mut rows = Rows::default();
+        let mut stack: Vec<String> = Vec::new();
+        // depth of the row being read
+        let mut depth = None;
+        let mut text = String::new();
+        loop {
+            let event = reader
+                .read_event()
+                .map_err(|e| anyhow!("Failed to parse XML: {}", e))?;
+            let (start, end) = match &event {
+                Event::Start(e) => (Some(e), false),
+                Event::Empty(e) => (Some(e), true),
+                Event::End(_) => (None, true),
+                Event::Text(e) => {
+                    text.push_str(&e.xml10_content()?);
+                    continue;
+                }
+                Event::CData(e) => {
+                    text.push_str(&e.decode()?);
+                    continue;
+                }
+                Event::GeneralRef(e) => {
+                    // undeclared entities are kept as written
+                    let entity = format!("&{};", e.decode()?);
+                    match quick_xml::escape::unescape(&entity) {
+                        Ok(value) => text.push_str(&value),
+                        Err(_) => text.push_str(&entity),
+                    }
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            if let Some(e) = start {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                stack.push(name);
+                text.clear();
+                if depth.is_none() && is_row(&stack) {
+                    depth = Some(stack.len());
+                    rows.start();
+                }
+                if let Some(depth) = depth {
+                    let prefix = stack[depth..].join(".");
+                    attributes(e, &prefix, &mut rows)?;
+                }
+            }
+            if end {
+                if let Some(row) = depth {
+                    let value = text.trim();
+                    if !value.is_empty() {
+                        let name = match stack.len() == row {
+                            true => stack[row - 1].clone(),
+                            false => stack[row..].join("."),
+                        };
+                        rows.set(&name, value);
+                    }
+                    if stack.len() == row {
+                        depth = None;
+                    }
+                }
+                text.clear();
+                stack.pop();
+            }
+        }
+
+        if rows.count == 0 {
+            return Err(match options.row_path {
+                Some(path) => anyhow!("No {} elements in the XML", path),
+                None => anyhow!("The XML root element has no children"),
+            });
+        }
+        Ok(DataSet(rows.into_frame()?))
+    }
+}
+
+/// Add the attributes of an element, namespace declarations aside.
+fn attributes(element: &BytesStart, prefix: &str, rows: &mut Rows) -> Result<()> {
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|e| anyhow!("Failed to parse XML: {}", e))?;
+        if attribute.key.as_namespace_binding().is_some() {
+            continue;
+        }
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+        let name = match prefix.is_empty() {
+            true => key,
+            false => format!("{}.{}", prefix, key),
+        };
+        let value = attribute.normalized_value(XmlVersion::Implicit1_0)?;
+        rows.set(&name, value.trim());
+    }
+    Ok(())
+}
+
+/// The table named by its zero based index or its id, a number past the
+/// last table being taken for an id such as `2024`.
+fn find_table<'a, 'b>(tables: &'b [ElementRef<'a>], table: &str) -> Option<&'b ElementRef<'a>> {
+    let id = table.trim_start_matches('#');
+    table
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| tables.get(index))
+        .or_else(|| tables.iter().find(|t| t.value().attr("id") == Some(id)))
+}
+
+/// Whether the HTML page `data` has the table named by `table`, telling a
+/// URL fragment naming a table from one naming another part of the page.
+pub(crate) fn has_table(data: &[u8], table: &str) -> bool {
+    let html = Html::parse_document(&String::from_utf8_lossy(data));
+    let tables: Vec<_> = html.select(&selector("table")).collect();
+    find_table(&tables, table).is_some()
+}
+
+impl Load for HtmlLoader {
+    type Error = anyhow::Error;
+
+    /// The header is the first row when it is in a `<thead>` or made of
+    /// `<th>` cells, and the columns are `column1`, `column2`, ... otherwise.
+    /// Cells spanning several columns repeat their value.
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let HtmlLoader(data, options) = self;
+        let html = Html::parse_document(&String::from_utf8_lossy(&data));
+        let tables: Vec<_> = html.select(&selector("table")).collect();
+        let table = match options.table.as_deref() {
+            None => tables.first(),
+            Some(table) => find_table(&tables, table),
+        };
+        let table = table.ok_or_else(|| match &options.table {
+            Some(table) => anyhow!(
+                "Table {} not found, the page has {} tables",
+                table,
+                tables.len()
+            ),
+            None => anyhow!("No <table> in the HTML"),
+        })?;
+
+        // rows of nested tables belong to those
+        let rows: Vec<_> = table
+            .select(&selector("tr"))
+            .filter(|tr| {
+                let parent = tr
+                    .ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .find(|e| e.value().name() == "table");
+                parent.map(|e| e.id()) == Some(table.id())
+            })
+            .collect();
+        let mut rows = rows.into_iter().peekable();
+        let header = rows.next_if(|tr| {
+            let in_head = tr
+                .parent()
+                .and_then(ElementRef::wrap)
+                .is_some_and(|e| e.value().name() == "thead");
+            let cells = cells(tr);
+            in_head || (!cells.is_empty() && cells.iter().all(|(name, _)| name == "th"))
+        });
+        let header: Vec<_> = header
+            .map(|tr| cells(&tr).into_iter().map(|(_, text)| text).collect())
+            .unwrap_or_default();
+        let rows: Vec<Vec<String>> = rows
+            .map(|tr| cells(&tr).into_iter().map(|(_, text)| text).collect())
+            .collect();
+
+        let width = rows
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .max(header.len());
+        let mut names: Vec<String> = Vec::with_capacity(width);
+        let mut series = Vec::with_capacity(width);
+        for i in 0..width {
+            let mut name = match header.get(i) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => format!("column{}", i + 1),
+            };
+            if names.contains(&name) {
+                name = format!("{}_{}", name, i + 1);
+            }
+            let values = rows
+                .iter()
+                .map(|row| row.get(i).filter(|v| !v.is_empty()).cloned())
+                .collect();
+            series.push(infer(&name, values)?);
+            names.push(name);
+        }
+        Ok(DataSet(DataFrame::new(series)?))
+    }
+}
+
+fn selector(selector: &str) -> Selector {
+    Selector::parse(selector).expect("valid selector")
+}
+
+/// The `td` and `th` cells of a row with their whitespace collapsed.
+fn cells(row: &ElementRef) -> Vec<(String, String)> {
+    let mut cells = Vec::new();
+    for cell in row.children().filter_map(ElementRef::wrap) {
+        let name = cell.value().name();
+        if name != "td" && name != "th" {
+            continue;
+        }
+        let text: String = cell.text().collect();
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let span = cell
+            .value()
+            .attr("colspan")
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(1)
+            .clamp(1, 1000);
+        for _ in 0..span {
+            cells.push((name.to_string(), text.clone()));
+        }
+    }
+    cells
+}
+
+/// Values of the rows, a column per name seen.
+#[derive(Default)]
+struct Rows {
+    names: Vec<String>,
+    values: Vec<Vec<Option<String>>>,
+    count: usize,
+}
+
+impl Rows {
+    fn start(&mut self) {
+        for column in &mut self.values {
+            column.push(None);
+        }
+        self.count += 1;
+    }
+
+    /// Set `name` in the current row, joining repeated values.
+    fn set(&mut self, name: &str, value: &str) {
+        let index = match self.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.values.push(vec![None; self.count]);
+                self.names.len() - 1
+            }
+        };
+        match &mut self.values[index][self.count - 1] {
+            Some(current) => {
+                current.push_str(", ");
+                current.push_str(value);
+            }
+            current => *current = Some(value.to_string()),
+        }
+    }
+
+    fn into_frame(self) -> Result<DataFrame> {
+        let series = self
+            .names
+            .iter()
+            .zip(self.values)
+            .map(|(name, values)| infer(name, values))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DataFrame::new(series)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml() {
+        let data = br#"<?xml version="1.0"?>
+            <catalog xmlns:x="urn:x">
+              <book id="1" x:lang="en">
+                <title>Rust &amp; You</title>
+                <author><name>Ann</name></author>
+                <tag>a</tag><tag>b</tag>
+                <price>10.5</price>
+              </book>
+              <book id="2"><title><![CDATA[<XML>]]></title><price>7</price></book>
+            </catalog>"#
+            .to_vec();
+        let df = XmlLoader(data.clone(), XmlOptions::default())
+            .load()
+            .unwrap()
+            .0;
+        assert_eq!(
+            df.get_column_names(),
+            ["id", "lang", "title", "author.name", "tag", "price"]
+        );
+        assert_eq!(df.column("id").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("price").unwrap().dtype(), &DataType::Float64);
+        let titles: Vec<_> = df
+            .column("title")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(titles, [Some("Rust & You"), Some("<XML>")]);
+        assert_eq!(
+            df.column("tag").unwrap().str().unwrap().get(0),
+            Some("a, b")
+        );
+
+        let options = XmlOptions::default().with_row_path(Some("book/tag".into()));
+        let df = XmlLoader(data.clone(), options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["tag"]);
+        assert_eq!(df.height(), 2);
+
+        let options = XmlOptions::default().with_row_path(Some("/book".into()));
+        assert!(XmlLoader(data, options).load().is_err());
+    }
+
+    #[test]
+    fn html_tables() {
+        let data = br#"<html><body>
+            <table><tr><td>menu</td></tr></table>
+            <table id="sales">
+              <thead><tr><th>Region</th><th colspan="2">Sales</th></tr></thead>
+              <tbody>
+                <tr><td>North <b>East</b></td><td>1</td><td>2.5</td></tr>
+                <tr><td>South</td><td>3</td><td>
+                  <table><tr><td>nested</td></tr></table></td></tr>
+              </tbody>
+            </table>
+            </body></html>"#
+            .to_vec();
+        let load = |table: Option<&str>| {
+            let options = HtmlOptions::default().with_table(table.map(String::from));
+            HtmlLoader(data.clone(), options).load()
+        };
+
+        let df = load(None).unwrap().0;
+        assert_eq!(df.get_column_names(), ["column1"]);
+
+        let df = load(Some("sales")).unwrap().0;
+        assert_eq!(df.get_column_names(), ["Region", "Sales", "Sales_3"]);
+        assert_eq!(df.height(), 2);
+        assert_eq!(
+            df.column("Region").unwrap().str().unwrap().get(0),
+            Some("North East")
+        );
+        assert_eq!(df.column("Sales").unwrap().dtype(), &DataType::Int64);
+        assert!(load(Some("1")).unwrap().0.equals(&df));
+
+        let err = load(Some("#missing")).unwrap_err();
+        assert!(err.to_string().contains("3 tables"), "{}", err);
+
+        // an empty first row is not a header, a number past the tables an id
+        let data = br#"<table id="2024"><tr></tr><tr><td>1</td></tr></table>"#;
+        let options = HtmlOptions::default().with_table(Some("2024".into()));
+        let df = HtmlLoader(data.to_vec(), options).load().unwrap().0;
+        assert_eq!(df.get_column_names(), ["column1"]);
+        assert_eq!(df.height(), 2);
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::cache::Cache;
+use crate::http::{seconds, HttpOptions};
+use crate::loader::{CsvOptions, ExcelOptions, Format, FwfOptions, JsonOptions};
+use crate::markup::{HtmlOptions, XmlOptions};
+
+/// Options controlling how a query reads its data source.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Add a `_filename` column holding the path each row was read from.
+    pub filename_column: bool,
+    /// Format of the source data, detected from the content when unset.
+    pub format: Option<Format>,
+    /// How CSV data is read.
+    pub csv: CsvOptions,
+    /// How JSON data is read.
+    pub json: JsonOptions,
+    /// How spreadsheets are read.
+    pub excel: ExcelOptions,
+    /// How fixed-width data is read.
+    pub fwf: FwfOptions,
+    /// How XML is read.
+    pub xml: XmlOptions,
+    /// How HTML tables are read.
+    pub html: HtmlOptions,
+    /// Encoding of text sources, e.g. `gbk`, `big5` or `utf-16le`, told by
+    /// a byte order mark or the HTTP charset, else UTF-8, when unset.
+    pub encoding: Option<String>,
+    /// Regular expression of `Format::Regex`, its named groups are the
+    /// columns.
+    pub pattern: Option<String>,
+    /// HTTP options applied to every HTTP source.
+    pub http: HttpOptions,
+    /// HTTP options for sources on a given `host` or `host:port`.
+    pub http_hosts: HashMap<String, HttpOptions>,
+    /// Cache for HTTP responses, nothing is cached when unset.
+    pub cache: Option<Cache>,
+}
+
+/// Settings read from the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    hosts: HashMap<String, HttpOptions>,
+    cache: Option<CacheConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CacheConfig {
+    dir: Option<PathBuf>,
+    #[serde(default, deserialize_with = "seconds")]
+    ttl: Option<Duration>,
+    authenticated: Option<bool>,
+}
+
+impl QueryOptions {
+    pub fn with_filename_column(mut self, filename_column: bool) -> Self {
+        self.filename_column = filename_column;
+        self
+    }
+
+    pub fn with_format(mut self, format: Option<Format>) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_csv(mut self, csv: CsvOptions) -> Self {
+        self.csv = csv;
+        self
+    }
+
+    pub fn with_json(mut self, json: JsonOptions) -> Self {
+        self.json = json;
+        self
+    }
+
+    pub fn with_excel(mut self, excel: ExcelOptions) -> Self {
+        self.excel = excel;
+        self
+    }
+
+    pub fn with_fwf(mut self, fwf: FwfOptions) -> Self {
+        self.fwf = fwf;
+        self
+    }
+
+    pub fn with_xml(mut self, xml: XmlOptions) -> Self {
+        self.xml = xml;
+        self
+    }
+
+    pub fn with_html(mut self, html: HtmlOptions) -> Self {
+        self.html = html;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: Option<String>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: Option<String>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpOptions) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn with_http_host(mut self, host: impl Into<String>, http: HttpOptions) -> Self {
+        self.http_hosts.insert(host.into(), http);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Load the per-host and cache settings of a TOML config file, e.g.
+    ///
+    /// ```toml
+    /// [hosts."api.example.com"]
+    /// auth = { bearer = "${EXAMPLE_TOKEN}" }
+    /// headers = { X-Api-Key = "${EXAMPLE_KEY}" }
+    ///
+    /// [cache]
+    /// ttl = 600
+    /// authenticated = false
+    /// ```
+    ///
+    /// A `[cache]` section enables the cache if it is not already.
+    pub fn with_config_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let config: Config = toml::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        self.http_hosts.extend(config.hosts);
+
+        if let Some(CacheConfig {
+            dir,
+            ttl,
+            authenticated,
+        }) = config.cache
+        {
+            let cache = match (self.cache.take(), dir) {
+                (Some(cache), Some(dir)) => Some(cache.with_dir(dir)),
+                (Some(cache), None) => Some(cache),
+                (None, Some(dir)) => Some(Cache::new(dir)),
+                (None, None) => Cache::default_dir().map(Cache::new),
+            };
+            let cache = match ttl {
+                Some(ttl) => cache.map(|c| c.with_ttl(ttl)),
+                None => cache,
+            };
+            self.cache = match authenticated {
+                Some(authenticated) => cache.map(|c| c.with_authenticated(authenticated)),
+                None => cache,
+            };
+        }
+        Ok(self)
+    }
+
+    /// HTTP options for `url`: the settings of its host overlaid with the
+    /// global ones.
+    pub(crate) fn http_for(&self, url: &str) -> HttpOptions {
+        let host = reqwest::Url::parse(url).ok().and_then(|url| {
+            let host = url.host_str()?;
+            url.port()
+                .and_then(|port| self.http_hosts.get(&format!("{}:{}", host, port)))
+                .or_else(|| self.http_hosts.get(host))
+                .cloned()
+        });
+        host.unwrap_or_default().merge(&self.http)
+    }
+}
+
+/// Where the config file is looked up: `$XQ_CONFIG`, or `xq/config.toml`
+/// in `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("XQ_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("xq").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Auth;
+
+    #[test]
+    fn config_file_hosts() {
+        let path = std::env::temp_dir().join("xq_test_config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [hosts."api.example.com"]
+            auth = { bearer = "${EXAMPLE_TOKEN}" }
+            headers = { X-Api-Key = "${EXAMPLE_KEY}" }
+
+            [hosts."localhost:8080"]
+            auth = { basic = { username = "me", password = "${PASSWORD}" } }
+            "#,
+        )
+        .unwrap();
+
+        let options = QueryOptions::default()
+            .with_http(HttpOptions::default().with_user_agent("xq-test"))
+            .with_config_file(&path)
+            .unwrap();
+
+        let http = options.http_for("https://api.example.com/v1/items");
+        assert!(matches!(http.auth, Some(Auth::Bearer(_))));
+        assert_eq!(http.headers["X-Api-Key"], "${EXAMPLE_KEY}");
+        assert_eq!(http.user_agent.as_deref(), Some("xq-test"));
+
+        let http = options.http_for("http://localhost:8080/data.csv");
+        assert!(matches!(http.auth, Some(Auth::Basic { .. })));
+
+        let http = options.http_for("http://localhost/data.csv");
+        assert!(http.auth.is_none());
+        assert!(options.cache.is_none());
+
+        std::fs::write(&path, "[cache]\ndir = \"/tmp/xq-cache\"\nttl = 60\n").unwrap();
+        let options = QueryOptions::default().with_config_file(&path).unwrap();
+        assert_eq!(options.cache.unwrap().dir(), Path::new("/tmp/xq-cache"));
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use anyhow::{anyhow, Context, Result};
+use polars::prelude::*;
+use polars_parquet::parquet::statistics::Statistics;
+use polars_parquet::read::{
+    infer_schema, read_metadata, FileReader, PrimitiveConvertedType, PrimitiveLogicalType,
+    RowGroupMetaData,
+};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+
+use crate::convert::Sql;
+use crate::http::HttpOptions;
+use crate::loader::{Format, Load, ParquetLoader};
+use crate::partition::conjuncts;
+
+/// Bytes requested for the footer up front, enough for most metadata.
+const FOOTER_SIZE: u64 = 64 * 1024;
+/// Byte ranges closer than this are fetched with a single request.
+const MERGE_GAP: u64 = 64 * 1024;
+
+/// Whether `url` is read as a remote Parquet file: its format is Parquet,
+/// or it is not set and the path ends in `.parquet`.
+pub fn is_remote(url: &str, format: Option<Format>) -> bool {
+    url.starts_with("http")
+        && format
+            .or_else(|| Format::from_extension(url))
+            .is_some_and(|f| f == Format::Parquet)
+}
+
+/// Read a Parquet file over HTTP with range requests: the footer first,
+/// then only the column chunks `sql` reads, of the row groups whose
+/// statistics do not rule out its `WHERE` condition.
+///
+/// Servers without range support get the whole file downloaded.
+pub async fn read_remote(url: &str, sql: &Sql<'_>, http: &HttpOptions) -> Result<DataFrame> {
+    let client = http.client()?;
+    let fetch = |range: String| {
+        let client = client.clone();
+        async move {
+            let mut headers = HeaderMap::new();
+            headers.insert(RANGE, HeaderValue::from_str(&range)?);
+            http.send_with(&client, url, headers).await
+        }
+    };
+
+    let tail = fetch(format!("bytes=-{}", FOOTER_SIZE)).await?;
+    if tail.status != StatusCode::PARTIAL_CONTENT {
+        tracing::debug!("{} does not support range requests", url);
+        return Ok(ParquetLoader(tail.body).load()?.0);
+    }
+    let len = tail
+        .headers
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, len)| len.parse::<u64>().ok())
+        .ok_or_else(|| anyhow!("{} sent no usable Content-Range", url))?;
+
+    let mut file = Ranges::new(len);
+    let tail_start = len - tail.body.len() as u64;
+    let footer = footer_len(&tail.body).with_context(|| format!("{} is not Parquet", url))?;
+    if footer > tail.body.len() as u64 && tail_start > 0 {
+        let start = len.saturating_sub(footer);
+        let rest = fetch(format!("bytes={}-{}", start, tail_start - 1)).await?;
+        file.insert(start, rest.body);
+    }
+    file.insert(tail_start, tail.body);
+
+    let metadata = read_metadata(&mut file)?;
+    let mut schema = infer_schema(&metadata)?;
+    if let Some(columns) = sql.columns() {
+        let first = schema.fields.first().cloned();
+        schema.fields.retain(|f| columns.contains(&f.name));
+        // a row count still needs a column to count
+        if schema.fields.is_empty() {
+            schema.fields.extend(first);
+        }
+    }
+
+    let conditions: Vec<&Expr> = sql.condition.as_ref().map(conjuncts).unwrap_or_default();
+    let total = metadata.row_groups.len();
+    let row_groups: Vec<RowGroupMetaData> = metadata
+        .row_groups
+        .into_iter()
+        .filter(|rg| conditions.iter().all(|c| may_match(rg, c)))
+        .collect();
+
+    let mut ranges: Vec<(u64, u64)> = row_groups
+        .iter()
+        .flat_map(|rg| rg.columns())
+        .filter(|c| {
+            let name = &c.descriptor().path_in_schema[0];
+            schema.fields.iter().any(|f| &f.name == name)
+        })
+        .map(|c| {
+            let (start, len) = c.byte_range();
+            (start, start + len)
+        })
+        .collect();
+    ranges.sort_unstable();
+    let ranges = ranges
+        .into_iter()
+        .fold(Vec::new(), |mut merged: Vec<(u64, u64)>, (s, e)| {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 + MERGE_GAP => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+            merged
+        });
+    tracing::debug!(
+        "reading {} of {} row groups of {} in {} requests",
+        row_groups.len(),
+        total,
+        url,
+        ranges.len()
+    );
+    for (start, end) in ranges {
+        let resp = fetch(format!("bytes={}-{}", start, end - 1)).await?;
+        file.insert(start, resp.body);
+    }
+
+    let fields = schema.fields.clone();
+    let mut df = DataFrame::empty_with_schema(&Schema::from_iter(fields.iter().map(Field::from)));
+    for batch in FileReader::new(file, row_groups, schema, None, None, None) {
+        df.vstack_mut(&DataFrame::try_from((batch?, fields.as_slice()))?)?;
+    }
+    Ok(df)
+}
+
+/// The length of the footer, metadata included, from the end of a file.
+fn footer_len(tail: &[u8]) -> Result<u64> {
+    match tail {
+        [.., a, b, c, d, b'P', b'A', b'R', b'1'] => {
+            Ok(u32::from_le_bytes([*a, *b, *c, *d]) as u64 + 8)
+        }
+        _ => Err(anyhow!("missing PAR1 magic bytes")),
+    }
+}
+
+/// Whether the statistics of `row_group` allow rows matching
+/// `condition`, a comparison of a column with a literal.
+fn may_match(row_group: &RowGroupMetaData, condition: &Expr) -> bool {
+    let Expr::BinaryExpr { left, op, right } = condition else {
+        return true;
+    };
+    let (name, op, value) = match (left.as_ref(), right.as_ref()) {
+        (Expr::Column(name), Expr::Literal(value)) => (name, *op, value),
+        (Expr::Literal(value), Expr::Column(name)) => {
+            let op = match op {
+                Operator::Lt => Operator::Gt,
+                Operator::LtEq => Operator::GtEq,
+                Operator::Gt => Operator::Lt,
+                Operator::GtEq => Operator::LtEq,
+                op => *op,
+            };
+            (name, op, value)
+        }
+        _ => return true,
+    };
+    let Some(column) = row_group
+        .columns()
+        .iter()
+        .find(|c| c.descriptor().path_in_schema == [name.to_string()])
+    else {
+        return true;
+    };
+    let Some(Ok(stats)) = column.statistics() else {
+        return true;
+    };
+
+    match (stats, value) {
+        (Statistics::Int32(s), v) if plain(&s.primitive_type) => compare(
+            op,
+            s.min_value.map(f64::from),
+            s.max_value.map(f64::from),
+            number(v),
+        ),
+        (Statistics::Int64(s), v) if plain(&s.primitive_type) => compare(
+            op,
+            s.min_value.map(|n| n as f64),
+            s.max_value.map(|n| n as f64),
+            number(v),
+        ),
+        (Statistics::Float(s), v) => compare(
+            op,
+            s.min_value.map(f64::from),
+            s.max_value.map(f64::from),
+            number(v),
+        ),
+        (Statistics::Double(s), v) => compare(op, s.min_value, s.max_value, number(v)),
+        (Statistics::Binary(s), LiteralValue::String(v)) if text(&s.primitive_type) => {
+            compare(op, s.min_value, s.max_value, Some(v.as_bytes().to_vec()))
+        }
+        _ => true,
+    }
+}
+
+/// Whether values between `min` and `max` may compare with `value` by
+/// `op`, true when any of them is unknown.
+fn compare<T: PartialOrd>(op: Operator, min: Option<T>, max: Option<T>, value: Option<T>) -> bool {
+    let (Some(min), Some(max), Some(value)) = (min, max, value) else {
+        return true;
+    };
+    match op {
+        Operator::Eq => min <= value && value <= max,
+        Operator::NotEq => !(min == value && max == value),
+        Operator::Lt => min < value,
+        Operator::LtEq => min <= value,
+        Operator::Gt => max > value,
+        Operator::GtEq => max >= value,
+        _ => true,
+    }
+}
+
+fn number(value: &LiteralValue) -> Option<f64> {
+    match value {
+        LiteralValue::Float64(n) => Some(*n),
+        LiteralValue::Int64(n) => Some(*n as f64),
+        _ => None,
+    }
+}
+
+/// Integers whose statistics compare like their values, unlike e.g.
+/// decimals or timestamps.
+fn plain(t: &polars_parquet::parquet::schema::types::PrimitiveType) -> bool {
+    matches!(
+        t.logical_type,
+        None | Some(PrimitiveLogicalType::Integer(_))
+    ) && matches!(
+        t.converted_type,
+        None | Some(
+            PrimitiveConvertedType::Int8
+                | PrimitiveConvertedType::Int16
+                | PrimitiveConvertedType::Int32
+                | PrimitiveConvertedType::Int64
+                | PrimitiveConvertedType::Uint8
+                | PrimitiveConvertedType::Uint16
+                | PrimitiveConvertedType::Uint32
+                | PrimitiveConvertedType::Uint64
+        )
+    )
+}
+
+fn text(t: &polars_parquet::parquet::schema::types::PrimitiveType) -> bool {
+    t.logical_type == Some(PrimitiveLogicalType::String)
+        || t.converted_type == Some(PrimitiveConvertedType::Utf8)
+}
+
+/// A file of which only some byte ranges were downloaded.
+struct Ranges {
+    len: u64,
+    pos: u64,
+    chunks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Ranges {
+    fn new(len: u64) -> Self {
+        Self {
+            len,
+            pos: 0,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, start: u64, data: Vec<u8>) {
+        self.chunks.insert(start, data);
+    }
+}
+
+impl Read for Ranges {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk = self
+            .chunks
+            .range(..=self.pos)
+            .rev()
+            .find(|(start, data)| self.pos < *start + data.len() as u64);
+        let Some((start, data)) = chunk else {
+            return Err(io::Error::other(format!(
+                "byte {} was not downloaded",
+                self.pos
+            )));
+        };
+        let data = &data[(self.pos - start) as usize..];
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Ranges {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::other("seek before the start of the file"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XQDialect;
+    use sqlparser::parser::Parser;
+    use std::sync::Mutex;
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    /// Serves byte ranges of a file and records the bytes sent.
+    struct RangeServer(Vec<u8>, Arc<Mutex<usize>>);
+
+    impl Respond for RangeServer {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let len = self.0.len();
+            let range = request.headers.get("range").unwrap().to_str().unwrap();
+            let range = range.strip_prefix("bytes=").unwrap();
+            let (start, end) = match range.split_once('-').unwrap() {
+                ("", n) => (len - n.parse::<usize>().unwrap().min(len), len - 1),
+                (s, e) => (s.parse().unwrap(), e.parse().unwrap()),
+            };
+            *self.1.lock().unwrap() += end + 1 - start;
+            ResponseTemplate::new(206)
+                .insert_header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                .set_body_bytes(self.0[start..=end].to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn range_requests() {
+        let mut df = df! {
+            "id" => (0..10_000i64).collect::<Vec<_>>(),
+            "name" => (0..10_000).map(|i| format!("name-{}", i)).collect::<Vec<_>>(),
+            "payload" => (0..10_000).map(|i| format!("{:0>200}", i)).collect::<Vec<_>>(),
+        }
+        .unwrap();
+        let mut file = Vec::new();
+        ParquetWriter::new(&mut file)
+            .with_row_group_size(Some(1000))
+            .with_compression(ParquetCompression::Uncompressed)
+            .finish(&mut df)
+            .unwrap();
+
+        let server = MockServer::start().await;
+        let sent = Arc::new(Mutex::new(0));
+        Mock::given(path("/data.parquet"))
+            .respond_with(RangeServer(file.clone(), sent.clone()))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/data.parquet", server.uri());
+        let sql = format!("SELECT name FROM {} WHERE id >= 9500", url);
+        let statement = &Parser::parse_sql(&XQDialect, &sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        assert!(is_remote(&url, None));
+
+        let df = read_remote(&url, &sql, &HttpOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(df.get_column_names(), ["id", "name"]);
+        // only the last row group is read
+        assert_eq!(df.height(), 1000);
+        assert!(*sent.lock().unwrap() < file.len() / 10);
+    }
+}
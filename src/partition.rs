@@ -0,0 +1,246 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use polars::prelude::*;
+use reqwest::Url;
+
+const INDEX: &str = "__xq_partition_index";
+
+/// The partition keys of a location with their values, typed alike for a
+/// key across all locations of a source.
+pub type Partitions = Vec<(String, LiteralValue)>;
+
+/// Hive-style `key=value` pairs found in the directories of `path`.
+pub fn parse(path: &Path) -> Vec<(String, String)> {
+    let Some(parent) = path.parent() else {
+        return Vec::new();
+    };
+
+    parent
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .filter_map(|name| name.split_once('='))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The partitions of every path. A key's values are integers when all of
+/// them parse as such, in every path, and strings otherwise.
+pub fn typed(paths: &[PathBuf]) -> Vec<Partitions> {
+    let parsed: Vec<_> = paths.iter().map(|p| parse(p)).collect();
+    let strings: Vec<&str> = parsed
+        .iter()
+        .flatten()
+        .filter(|(_, v)| v.parse::<i64>().is_err())
+        .map(|(k, _)| k.as_str())
+        .collect();
+    parsed
+        .iter()
+        .map(|partitions| {
+            partitions
+                .iter()
+                .map(|(key, value)| match value.parse::<i64>() {
+                    Ok(n) if !strings.contains(&key.as_str()) => {
+                        (key.clone(), LiteralValue::Int64(n))
+                    }
+                    _ => (key.clone(), LiteralValue::String(value.clone())),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The partitions in the path of a URL, e.g.
+/// `https://example.com/data/year=2024/part-0.parquet`.
+pub fn from_url(url: &str) -> Partitions {
+    let path = Url::parse(url)
+        .map(|url| PathBuf::from(url.path()))
+        .unwrap_or_default();
+    typed(&[path]).remove(0)
+}
+
+/// Drop the files whose partition keys can not satisfy `condition`.
+///
+/// Only the `AND`-ed parts of the condition that reference nothing but
+/// partition keys are evaluated, everything else is left to the query.
+pub fn prune(
+    files: Vec<(PathBuf, Partitions)>,
+    condition: Option<&Expr>,
+) -> Result<Vec<(PathBuf, Partitions)>> {
+    let Some(condition) = condition else {
+        return Ok(files);
+    };
+
+    let mut keys: Vec<&str> = files
+        .iter()
+        .flat_map(|(_, p)| p.iter().map(|(k, _)| k.as_str()))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let predicate = conjuncts(condition)
+        .into_iter()
+        .filter(|e| {
+            let columns = leaf_columns(e);
+            !columns.is_empty() && columns.iter().all(|c| keys.contains(&c.as_ref()))
+        })
+        .cloned()
+        .reduce(|a, b| a.and(b));
+    let Some(predicate) = predicate else {
+        return Ok(files);
+    };
+
+    let mut columns: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            let values: Vec<_> = files
+                .iter()
+                .map(|(_, p)| p.iter().find(|(k, _)| k == key).map(|(_, v)| v))
+                .collect();
+            series(key, &values)
+        })
+        .collect();
+    columns.push(Series::new(
+        INDEX,
+        (0..files.len() as u32).collect::<Vec<_>>(),
+    ));
+
+    let selected = DataFrame::new(columns)?
+        .lazy()
+        .filter(predicate)
+        .select([col(INDEX)])
+        .collect()?;
+    let selected: Vec<_> = selected.column(INDEX)?.u32()?.into_no_null_iter().collect();
+
+    Ok(files
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| selected.contains(&(*i as u32)))
+        .map(|(_, f)| f)
+        .collect())
+}
+
+/// Add the partition keys as columns, unless the data already has them.
+pub fn attach(mut lf: LazyFrame, partitions: &[(String, LiteralValue)]) -> Result<LazyFrame> {
+    if partitions.is_empty() {
+        return Ok(lf);
+    }
+    let schema = lf.schema()?;
+    let columns: Vec<_> = partitions
+        .iter()
+        .filter(|(key, _)| !schema.contains(key))
+        .map(|(key, value)| Expr::Literal(value.clone()).alias(key))
+        .collect();
+    Ok(lf.with_columns(columns))
+}
+
+fn series(name: &str, values: &[Option<&LiteralValue>]) -> Series {
+    let ints: Option<Vec<_>> = values
+        .iter()
+        .map(|v| match v {
+            Some(LiteralValue::Int64(n)) => Some(Some(*n)),
+            Some(_) => None,
+            None => Some(None),
+        })
+        .collect();
+    match ints {
+        Some(ints) => Series::new(name, ints),
+        None => {
+            let strings: Vec<_> = values
+                .iter()
+                .map(|v| match v {
+                    Some(LiteralValue::String(s)) => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, strings)
+        }
+    }
+}
+
+/// The `AND`-ed parts of `expr`.
+pub(crate) fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            let mut exprs = conjuncts(left);
+            exprs.extend(conjuncts(right));
+            exprs
+        }
+        e => vec![e],
+    }
+}
+
+fn leaf_columns(expr: &Expr) -> Vec<Arc<str>> {
+    expr.into_iter()
+        .filter_map(|e| match e {
+            Expr::Column(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hive_partitions() {
+        let path = Path::new("/data/year=2024/month=05/part-0.csv");
+        assert_eq!(
+            parse(path),
+            vec![
+                ("year".to_string(), "2024".to_string()),
+                ("month".to_string(), "05".to_string())
+            ]
+        );
+        assert!(parse(Path::new("/data/plain/part-0.csv")).is_empty());
+    }
+
+    #[test]
+    fn prune_by_partition_keys() {
+        let files = vec![
+            PathBuf::from("/data/year=2023/region=eu/a.csv"),
+            PathBuf::from("/data/year=2024/region=eu/a.csv"),
+            PathBuf::from("/data/year=2024/region=us/a.csv"),
+        ];
+        let condition = col("year")
+            .eq(lit(2024.0))
+            .and(col("region").eq(lit("eu")))
+            .and(col("value").gt(lit(1)));
+
+        let partitions = typed(&files);
+        let files = files.into_iter().zip(partitions).collect();
+        let pruned: Vec<_> = prune(files, Some(&condition))
+            .unwrap()
+            .into_iter()
+            .map(|(f, _)| f)
+            .collect();
+        assert_eq!(
+            pruned,
+            vec![PathBuf::from("/data/year=2024/region=eu/a.csv")]
+        );
+    }
+
+    #[test]
+    fn type_keys_across_files() {
+        let files = [
+            PathBuf::from("/data/day=01/code=7/a.csv"),
+            PathBuf::from("/data/day=02/code=x/a.csv"),
+        ];
+        let partitions = typed(&files);
+        assert_eq!(partitions[0][0], ("day".into(), LiteralValue::Int64(1)));
+        // one string value makes every value of the key a string
+        assert_eq!(
+            partitions[0][1],
+            ("code".into(), LiteralValue::String("7".into()))
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use polars::prelude::*;
+use reqwest::Url;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::convert::TableFunction;
+
+/// A table or query of a SQLite database file.
+///
+/// As a URI, `sqlite:///path/to/app.db?table=events` or
+/// `sqlite:///path/to/app.db?query=select ...`, and as a table function,
+/// `sqlite('/path/to/app.db', 'events')` or
+/// `sqlite('/path/to/app.db', query => 'select ...')`.
+///
+/// Without a table or query, the database must hold a single table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqliteSource {
+    path: PathBuf,
+    relation: Option<Relation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Relation {
+    Table(String),
+    Query(String),
+}
+
+impl SqliteSource {
+    pub fn parse(uri: &str) -> Result<Self> {
+        let rest = uri
+            .strip_prefix("sqlite://")
+            .ok_or_else(|| anyhow!("{} is not a sqlite:// URI", uri))?;
+        let path = rest.split_once('?').map_or(rest, |(path, _)| path);
+        if path.is_empty() {
+            return Err(anyhow!("{} has no database path", uri));
+        }
+
+        let mut source = Self {
+            path: PathBuf::from(path),
+            relation: None,
+        };
+        // the path may hold characters a URL parser would mangle, only the
+        // query string is read from the parsed URL
+        let url = Url::parse(uri).with_context(|| format!("Invalid sqlite URI {}", uri))?;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "table" => source.relation = Some(Relation::Table(value.into_owned())),
+                "query" => source.relation = Some(Relation::Query(value.into_owned())),
+                key => return Err(anyhow!("Unknown sqlite parameter {}", key)),
+            }
+        }
+        Ok(source)
+    }
+
+    pub fn from_function(func: &TableFunction) -> Result<Self> {
+        func.check(2, &["path", "table", "query"])?;
+        let path = func
+            .string(0, "path")?
+            .ok_or_else(|| anyhow!("sqlite() needs the path of a database file"))?;
+        let relation = match (func.string(1, "table")?, func.string(usize::MAX, "query")?) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("sqlite() takes a table or a query, not both"))
+            }
+            (Some(table), None) => Some(Relation::Table(table)),
+            (None, Some(query)) => Some(Relation::Query(query)),
+            (None, None) => None,
+        };
+        Ok(Self {
+            path: PathBuf::from(path),
+            relation,
+        })
+    }
+
+    /// Read the table or query result, on a blocking thread.
+    pub async fn load(self) -> Result<DataFrame> {
+        tokio::task::spawn_blocking(move || self.load_blocking()).await?
+    }
+
+    fn load_blocking(&self) -> Result<DataFrame> {
+        if !self.path.is_file() {
+            return Err(anyhow!("{} does not exist", self.path.display()));
+        }
+        let conn = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        let sql = match &self.relation {
+            Some(Relation::Table(table)) => format!("SELECT * FROM {}", quote(table)),
+            Some(Relation::Query(query)) => query.clone(),
+            None => format!("SELECT * FROM {}", quote(&single_table(&conn, &self.path)?)),
+        };
+        tracing::debug!("reading {} from {}", sql, self.path.display());
+
+        let mut stmt = conn.prepare(&sql)?;
+        let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut columns: Vec<Vec<Value>> = vec![Vec::new(); names.len()];
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            for (i, column) in columns.iter_mut().enumerate() {
+                column.push(row.get(i)?);
+            }
+        }
+
+        let series = names
+            .iter()
+            .zip(columns)
+            .map(|(name, values)| to_series(name, values))
+            .collect();
+        Ok(DataFrame::new(series)?)
+    }
+}
+
+fn single_table(conn: &Connection, path: &std::path::Path) -> Result<String> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') \
+         AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    match tables.as_slice() {
+        [table] => Ok(table.clone()),
+        [] => Err(anyhow!("{} has no tables", path.display())),
+        _ => Err(anyhow!(
+            "{} has {} tables, pick one with ?table=<name>: {}",
+            path.display(),
+            tables.len(),
+            tables.join(", ")
+        )),
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// SQLite columns are dynamically typed, a column becomes the narrowest
+/// type holding all of its values: integer, float, binary or string.
+fn to_series(name: &str, values: Vec<Value>) -> Series {
+    let (mut ints, mut reals, mut texts, mut blobs) = (false, false, false, false);
+    for value in &values {
+        match value {
+            Value::Null => {}
+            Value::Integer(_) => ints = true,
+            Value::Real(_) => reals = true,
+            Value::Text(_) => texts = true,
+            Value::Blob(_) => blobs = true,
+        }
+    }
+
+    match (ints, reals, texts, blobs) {
+        (_, false, false, false) => {
+            let values: Vec<_> = values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Integer(n) => Some(n),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        (_, true, false, false) => {
+            let values: Vec<_> = values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Integer(n) => Some(n as f64),
+                    Value::Real(n) => Some(n),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        (false, false, false, true) => {
+            let values: Vec<_> = values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Blob(b) => Some(b),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        _ => {
+            let values: Vec<_> = values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Null => None,
+                    Value::Integer(n) => Some(n.to_string()),
+                    Value::Real(n) => Some(n.to_string()),
+                    Value::Text(s) => Some(s),
+                    Value::Blob(b) => Some(String::from_utf8_lossy(&b).into_owned()),
+                })
+                .collect();
+            Series::new(name, values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uri() {
+        let source = SqliteSource::parse("sqlite:///tmp/app.db?table=events").unwrap();
+        assert_eq!(source.path, PathBuf::from("/tmp/app.db"));
+        assert_eq!(source.relation, Some(Relation::Table("events".into())));
+
+        let source =
+            SqliteSource::parse("sqlite:///tmp/app.db?query=select%20*%20from%20t").unwrap();
+        assert_eq!(
+            source.relation,
+            Some(Relation::Query("select * from t".into()))
+        );
+
+        assert!(SqliteSource::parse("sqlite:///tmp/app.db?tbl=x").is_err());
+        assert!(SqliteSource::parse("sqlite://").is_err());
+    }
+
+    #[tokio::test]
+    async fn load_tables_and_queries() {
+        let path = std::env::temp_dir().join("xq_test_sqlite.db");
+        std::fs::remove_file(&path).ok();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE events (id INTEGER, name TEXT, score REAL, raw BLOB);
+             INSERT INTO events VALUES (1, 'a', 1.5, x'00'), (2, NULL, 2, NULL), (3, 'c', NULL, x'01');",
+        )
+        .unwrap();
+
+        let db = format!("sqlite://{}", path.display());
+        let df = SqliteSource::parse(&db).unwrap().load().await.unwrap();
+        assert_eq!(df.shape(), (3, 4));
+        let dtypes: Vec<_> = df.dtypes();
+        assert_eq!(
+            dtypes,
+            [
+                DataType::Int64,
+                DataType::String,
+                DataType::Float64,
+                DataType::Binary
+            ]
+        );
+
+        let uri = format!(
+            "sqlite://{}?query=select id from events where score > 1.6",
+            path.display()
+        );
+        let df = SqliteSource::parse(&uri).unwrap().load().await.unwrap();
+        assert_eq!(df.column("id").unwrap().i64().unwrap().get(0), Some(2));
+
+        conn.execute_batch("CREATE TABLE other (x TEXT)").unwrap();
+        let err = SqliteSource::parse(&db).unwrap().load().await.unwrap_err();
+        assert!(err.to_string().contains("events, other"));
+
+        std::fs::remove_file(path).ok();
+    }
+}